@@ -0,0 +1,420 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+use crate::analytics::index::TransactionIndex;
+use crate::analytics::labels::{LabelKind, LabelRegistry};
+use crate::common::address::Address;
+use crate::common::block::Block;
+use crate::common::blockchain::BlockChainNames;
+use crate::common::transaction::Transaction;
+
+const SECONDS_PER_YEAR: f64 = 31_557_600.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    AmountAbove { threshold: f64 },
+    // Spender's Address::last_transaction is at least this old. Evaluate before
+    // the address is updated with the transaction being checked.
+    DormantSpend { min_years: f64 },
+    LabeledReceiver { kind: LabelKind },
+    MinerMovingCoinbase,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters)]
+pub struct RuleConfig {
+    #[getset(get = "pub")]
+    name: String,
+
+    #[getset(get = "pub")]
+    #[serde(flatten)]
+    rule: Rule
+}
+
+impl RuleConfig {
+    pub fn new(name: String, rule: Rule) -> Self {
+        RuleConfig { name, rule }
+    }
+}
+
+// Lookups rules may need; anything missing just means that rule can't match
+#[derive(Default)]
+pub struct AlertContext<'a> {
+    pub chain: Option<BlockChainNames>,
+    pub addresses: Option<&'a HashMap<String, Address>>,
+    pub labels: Option<&'a LabelRegistry>,
+    pub index: Option<&'a TransactionIndex>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct Alert {
+    #[getset(get = "pub")]
+    rule_name: String,
+
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get_copy = "pub")]
+    block_height: i64,
+
+    #[getset(get_copy = "pub")]
+    date: i64,
+
+    #[getset(get = "pub")]
+    address_hash: Option<String>,
+
+    #[getset(get_copy = "pub")]
+    amount: f64,
+
+    #[getset(get = "pub")]
+    detail: String
+}
+
+pub trait AlertSink {
+    fn deliver(&mut self, alert: &Alert) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct ChannelSink {
+    sender: Sender<Alert>
+}
+
+impl ChannelSink {
+    pub fn new(sender: Sender<Alert>) -> Self {
+        ChannelSink { sender }
+    }
+}
+
+impl AlertSink for ChannelSink {
+    fn deliver(&mut self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        self.sender.send(alert.clone())?;
+        Ok(())
+    }
+}
+
+// One JSON alert per line
+pub struct JsonlSink<W: Write> {
+    writer: W
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlSink { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonlSink<fs::File> {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonlSink::new(file))
+    }
+}
+
+impl<W: Write> AlertSink for JsonlSink<W> {
+    fn deliver(&mut self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.writer, alert)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+pub struct CallbackSink<F: FnMut(&Alert)> {
+    callback: F
+}
+
+impl<F: FnMut(&Alert)> CallbackSink<F> {
+    pub fn new(callback: F) -> Self {
+        CallbackSink { callback }
+    }
+}
+
+impl<F: FnMut(&Alert)> AlertSink for CallbackSink<F> {
+    fn deliver(&mut self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        (self.callback)(alert);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct AlertEngine {
+    rules: Vec<RuleConfig>,
+    sinks: Vec<Box<dyn AlertSink>>
+}
+
+impl AlertEngine {
+    pub fn new() -> Self {
+        AlertEngine::default()
+    }
+
+    // JSON array of rules, e.g. [{"name": "whale", "type": "amount_above", "threshold": 1000.0}]
+    pub fn from_json(raw: &str) -> Result<Self, Box<dyn Error>> {
+        let rules: Vec<RuleConfig> = serde_json::from_str(raw)?;
+        Ok(AlertEngine { rules, sinks: vec![] })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        AlertEngine::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn rules(&self) -> &Vec<RuleConfig> {
+        &self.rules
+    }
+
+    pub fn add_rule(&mut self, rule: RuleConfig) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn AlertSink>) -> &mut Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn evaluate(&self, transaction: &Transaction, context: &AlertContext) -> Vec<Alert> {
+        self.rules.iter()
+            .flat_map(|rule| evaluate_rule(rule, transaction, context))
+            .collect()
+    }
+
+    // Evaluates and hands every match to each sink
+    pub fn process_transaction(&mut self, transaction: &Transaction, context: &AlertContext) -> Result<Vec<Alert>, Box<dyn Error>> {
+        let alerts = self.evaluate(transaction, context);
+        for alert in &alerts {
+            for sink in self.sinks.iter_mut() {
+                sink.deliver(alert)?;
+            }
+        }
+        Ok(alerts)
+    }
+
+    pub fn process_block(&mut self, block: &Block, context: &AlertContext) -> Result<Vec<Alert>, Box<dyn Error>> {
+        let mut alerts = vec![];
+        for transaction in block.transactions() {
+            alerts.extend(self.process_transaction(transaction, context)?);
+        }
+        Ok(alerts)
+    }
+}
+
+fn evaluate_rule(config: &RuleConfig, transaction: &Transaction, context: &AlertContext) -> Vec<Alert> {
+    let alert = |address_hash: Option<&String>, amount: f64, detail: String| Alert {
+        rule_name: config.name.clone(),
+        transaction_hash: transaction.hash().clone(),
+        block_height: transaction.block_height(),
+        date: transaction.date(),
+        address_hash: address_hash.cloned(),
+        amount,
+        detail
+    };
+    let amounts = transaction.transaction_amounts();
+
+    match &config.rule {
+        Rule::AmountAbove { threshold } => {
+            // Outputs paying back to an input address are change, not value moved
+            let total: f64 = amounts.iter()
+                .filter(|a| a.is_output())
+                .filter(|output| !amounts.iter().any(|a| a.is_input() && a.address_hash() == output.address_hash()))
+                .map(|output| output.amount())
+                .sum();
+            if total > *threshold {
+                return vec![alert(None, total, format!("{} transferred, above {}", total, threshold))]
+            }
+            vec![]
+        },
+        Rule::DormantSpend { min_years } => {
+            let addresses = match context.addresses {
+                Some(addresses) => addresses,
+                None => return vec![]
+            };
+            amounts.iter()
+                .filter(|a| a.is_input())
+                .filter_map(|input| {
+                    let address = addresses.get(input.address_hash())?;
+                    if address.last_transaction() <= 0 {
+                        return None
+                    }
+                    let years = (transaction.date() - address.last_transaction()) as f64 / SECONDS_PER_YEAR;
+                    if years < *min_years {
+                        return None
+                    }
+                    Some(alert(Some(input.address_hash()), input.amount().abs(), format!("spent after {:.1} years dormant", years)))
+                })
+                .collect()
+        },
+        Rule::LabeledReceiver { kind } => {
+            let (labels, chain) = match (context.labels, &context.chain) {
+                (Some(labels), Some(chain)) => (labels, chain),
+                _ => return vec![]
+            };
+            amounts.iter()
+                .filter(|a| a.is_output())
+                .filter_map(|output| {
+                    let label = labels.labels_for(chain, output.address_hash()).iter().find(|l| l.kind() == kind)?;
+                    Some(alert(Some(output.address_hash()), output.amount(), format!("received by {}", label.entity())))
+                })
+                .collect()
+        },
+        Rule::MinerMovingCoinbase => {
+            let index = match context.index {
+                Some(index) => index,
+                None => return vec![]
+            };
+            if transaction.is_coinbase() {
+                return vec![]
+            }
+            amounts.iter()
+                .filter(|a| a.is_input())
+                .filter(|input| {
+                    // The spent output has to be a block reward, not just any payment to a miner
+                    let funded_by_coinbase = input.vin_hash().as_ref()
+                        .and_then(|hash| index.get(hash))
+                        .map(|funding| funding.is_coinbase())
+                        .unwrap_or(false);
+                    funded_by_coinbase || index.spent_output(input).map(|output| output.is_coinbase()).unwrap_or(false)
+                })
+                .map(|input| alert(Some(input.address_hash()), input.amount().abs(), "coinbase output moved".to_string()))
+                .collect()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::alerts::*;
+    use crate::analytics::index::TransactionIndex;
+    use crate::analytics::labels::{AddressLabel, LabelKind, LabelRegistry};
+    use crate::common::address::Address;
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::transaction::Transaction;
+    use crate::test_fixtures::{spend_of, transaction_at};
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::sync::mpsc::channel;
+
+    const YEAR: i64 = 31_557_600;
+
+    fn transaction(date: i64) -> Transaction {
+        let mut transaction = transaction_at("hashy", date, false, &[], &[("exchange", 1499.0)]);
+        transaction.transaction_amounts_mut().insert(0, spend_of("coinbase", 0, "old_whale", "hashy", 1500.0, date));
+        transaction
+    }
+
+    // Block reward old_whale's input spends
+    fn coinbase_index() -> TransactionIndex {
+        let mut index = TransactionIndex::new();
+        index.insert_transaction(transaction_at("coinbase", YEAR, true, &[], &[("old_whale", 1500.0)]));
+        index
+    }
+
+    #[test]
+    fn rules_from_json_test() {
+        let raw = r#"[
+            {"name": "whale", "type": "amount_above", "threshold": 1000.0},
+            {"name": "dormant", "type": "dormant_spend", "min_years": 5.0},
+            {"name": "deposit", "type": "labeled_receiver", "kind": "exchange"},
+            {"name": "miner", "type": "miner_moving_coinbase"}
+        ]"#;
+        let engine = AlertEngine::from_json(raw).unwrap();
+
+        assert_eq!(engine.rules().len(), 4);
+        assert_eq!(engine.rules()[1].rule(), &Rule::DormantSpend { min_years: 5.0 });
+        assert!(AlertEngine::from_json(r#"[{"name": "bad", "type": "unknown"}]"#).is_err());
+    }
+
+    #[test]
+    fn evaluate_rules_test() {
+        let raw = r#"[
+            {"name": "whale", "type": "amount_above", "threshold": 1000.0},
+            {"name": "dormant", "type": "dormant_spend", "min_years": 5.0},
+            {"name": "deposit", "type": "labeled_receiver", "kind": "exchange"},
+            {"name": "miner", "type": "miner_moving_coinbase"}
+        ]"#;
+        let engine = AlertEngine::from_json(raw).unwrap();
+
+        let mut whale = Address::new("old_whale".to_string());
+        whale.set_last_transaction(YEAR);
+        whale.set_is_miner(true);
+        let addresses: HashMap<String, Address> = vec![("old_whale".to_string(), whale)].into_iter().collect();
+        let mut labels = LabelRegistry::new();
+        labels.insert(BlockChainNames::Bitcoin, AddressLabel::new("exchange".to_string(), LabelKind::Exchange, "Exchange".to_string(), "test".to_string(), 1.0));
+
+        let index = coinbase_index();
+        let context = AlertContext {
+            chain: Some(BlockChainNames::Bitcoin),
+            addresses: Some(&addresses),
+            labels: Some(&labels),
+            index: Some(&index)
+        };
+        let alerts = engine.evaluate(&transaction(7 * YEAR), &context);
+        let names: Vec<&String> = alerts.iter().map(|a| a.rule_name()).collect();
+        assert_eq!(names, vec!["whale", "dormant", "deposit", "miner"]);
+        assert_eq!(alerts[0].amount(), 1499.0);
+
+        let recent = engine.evaluate(&transaction(3 * YEAR), &AlertContext::default());
+        assert_eq!(recent.len(), 1);
+    }
+
+    #[test]
+    fn miner_spending_payment_test() {
+        let engine = AlertEngine::from_json(r#"[{"name": "miner", "type": "miner_moving_coinbase"}]"#).unwrap();
+        let mut whale = Address::new("old_whale".to_string());
+        whale.set_is_miner(true);
+        let addresses: HashMap<String, Address> = vec![("old_whale".to_string(), whale)].into_iter().collect();
+        let mut index = TransactionIndex::new();
+        index.insert_transaction(transaction_at("coinbase", YEAR, false, &[("payer", 1501.0)], &[("old_whale", 1500.0)]));
+
+        let context = AlertContext { addresses: Some(&addresses), index: Some(&index), ..Default::default() };
+        assert!(engine.evaluate(&transaction(7 * YEAR), &context).is_empty());
+        assert!(engine.evaluate(&transaction(7 * YEAR), &AlertContext { addresses: Some(&addresses), ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn amount_above_ignores_change_test() {
+        let engine = AlertEngine::from_json(r#"[{"name": "whale", "type": "amount_above", "threshold": 1000.0}]"#).unwrap();
+        let transaction = transaction_at("hashy", YEAR, false, &[("old_whale", 1500.0)], &[("exchange", 200.0), ("old_whale", 1299.0)]);
+
+        assert!(engine.evaluate(&transaction, &AlertContext::default()).is_empty());
+    }
+
+    #[test]
+    fn sinks_test() {
+        let (sender, receiver) = channel();
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+
+        let mut engine = AlertEngine::new();
+        engine.add_rule(RuleConfig::new("whale".to_string(), Rule::AmountAbove { threshold: 1000.0 }));
+        engine.add_sink(Box::new(ChannelSink::new(sender)));
+        engine.add_sink(Box::new(CallbackSink::new(move |_alert: &Alert| counter.set(counter.get() + 1))));
+
+        let alerts = engine.process_transaction(&transaction(YEAR), &AlertContext::default()).unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(count.get(), 1);
+        assert_eq!(receiver.recv().unwrap().rule_name(), &"whale".to_string());
+    }
+
+    #[test]
+    fn jsonl_sink_test() {
+        let mut sink = JsonlSink::new(vec![]);
+        let engine = AlertEngine::from_json(r#"[{"name": "whale", "type": "amount_above", "threshold": 1.0}]"#).unwrap();
+        for alert in engine.evaluate(&transaction(YEAR), &AlertContext::default()) {
+            sink.deliver(&alert).unwrap();
+            sink.deliver(&alert).unwrap();
+        }
+
+        let written = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        let alert: Alert = serde_json::from_str(written.lines().next().unwrap()).unwrap();
+        assert_eq!(alert.transaction_hash(), &"hashy".to_string());
+    }
+}
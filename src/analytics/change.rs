@@ -0,0 +1,355 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::HashSet;
+
+use crate::common::address::AddressType;
+use crate::common::block::Block;
+use crate::common::transaction::{Transaction, TransactionAmount, to_satoshis};
+
+// A single change detection heuristic. Scores are the confidence in [0, 1]
+// that each output is change, None when the heuristic has no opinion.
+pub trait ChangeHeuristic {
+    fn name(&self) -> &'static str;
+    fn score(&self, transaction: &Transaction, outputs: &[&TransactionAmount], context: &ChangeContext) -> Vec<Option<f64>>;
+}
+
+// Addresses seen before the transaction being scored
+#[derive(Debug, Clone, Default)]
+pub struct ChangeContext {
+    seen_addresses: HashSet<String>
+}
+
+impl ChangeContext {
+    pub fn new() -> Self {
+        ChangeContext { seen_addresses: HashSet::new() }
+    }
+
+    pub fn observe_transaction(&mut self, transaction: &Transaction) -> &mut Self {
+        for amount in transaction.transaction_amounts() {
+            self.seen_addresses.insert(amount.address_hash().clone());
+        }
+        self
+    }
+
+    pub fn observe_block(&mut self, block: &Block) -> &mut Self {
+        for transaction in block.transactions() {
+            self.observe_transaction(transaction);
+        }
+        self
+    }
+
+    pub fn is_seen(&self, address_hash: &str) -> bool {
+        self.seen_addresses.contains(address_hash)
+    }
+}
+
+// Change usually goes back to the same script type the wallet spends from
+pub struct AddressTypeHeuristic;
+
+impl ChangeHeuristic for AddressTypeHeuristic {
+    fn name(&self) -> &'static str {
+        "address_type"
+    }
+    fn score(&self, transaction: &Transaction, outputs: &[&TransactionAmount], _context: &ChangeContext) -> Vec<Option<f64>> {
+        let input_types: HashSet<AddressType> = transaction.transaction_amounts().iter()
+            .filter(|a| a.is_input())
+            .map(|a| AddressType::from_hash(a.address_hash()))
+            .collect();
+        if input_types.len() != 1 || input_types.contains(&AddressType::Unknown) {
+            return vec![None; outputs.len()]
+        }
+
+        let matches: Vec<bool> = outputs.iter()
+            .map(|o| input_types.contains(&AddressType::from_hash(o.address_hash())))
+            .collect();
+        // Only informative when the outputs disagree on type
+        if matches.iter().all(|m| *m) || !matches.iter().any(|m| *m) {
+            return vec![None; outputs.len()]
+        }
+        matches.into_iter().map(|m| Some(if m { 0.8 } else { 0.1 })).collect()
+    }
+}
+
+// Payments tend to be round amounts, change is whatever is left over
+pub struct RoundNumberHeuristic {
+    pub decimals: u32, // amounts with at most this many decimals count as round
+}
+
+impl Default for RoundNumberHeuristic {
+    fn default() -> Self {
+        RoundNumberHeuristic { decimals: 3 }
+    }
+}
+
+impl ChangeHeuristic for RoundNumberHeuristic {
+    fn name(&self) -> &'static str {
+        "round_number"
+    }
+    fn score(&self, _transaction: &Transaction, outputs: &[&TransactionAmount], _context: &ChangeContext) -> Vec<Option<f64>> {
+        let unit = 10_i64.pow(8_u32.saturating_sub(self.decimals));
+        let round: Vec<bool> = outputs.iter()
+            .map(|o| to_satoshis(o.amount()) % unit == 0)
+            .collect();
+        if round.iter().all(|r| *r) || !round.iter().any(|r| *r) {
+            return vec![None; outputs.len()]
+        }
+        round.into_iter().map(|r| Some(if r { 0.2 } else { 0.7 })).collect()
+    }
+}
+
+// Wallets send change to a fresh address, payees are often reused addresses.
+// Paying back to one of the inputs is treated as near certain change.
+pub struct FreshAddressHeuristic;
+
+impl ChangeHeuristic for FreshAddressHeuristic {
+    fn name(&self) -> &'static str {
+        "fresh_address"
+    }
+    fn score(&self, transaction: &Transaction, outputs: &[&TransactionAmount], context: &ChangeContext) -> Vec<Option<f64>> {
+        let inputs: HashSet<&String> = transaction.transaction_amounts().iter()
+            .filter(|a| a.is_input())
+            .map(|a| a.address_hash())
+            .collect();
+        if outputs.iter().any(|o| inputs.contains(o.address_hash())) {
+            return outputs.iter()
+                .map(|o| Some(if inputs.contains(o.address_hash()) { 0.95 } else { 0.05 }))
+                .collect()
+        }
+
+        let fresh: Vec<bool> = outputs.iter().map(|o| !context.is_seen(o.address_hash())).collect();
+        if fresh.iter().filter(|f| **f).count() != 1 {
+            return vec![None; outputs.len()]
+        }
+        fresh.into_iter().map(|f| Some(if f { 0.75 } else { 0.15 })).collect()
+    }
+}
+
+// Some wallets always append change last. BIP69 sorted outputs carry no signal.
+pub struct OutputOrderHeuristic;
+
+impl ChangeHeuristic for OutputOrderHeuristic {
+    fn name(&self) -> &'static str {
+        "output_order"
+    }
+    fn score(&self, _transaction: &Transaction, outputs: &[&TransactionAmount], _context: &ChangeContext) -> Vec<Option<f64>> {
+        let bip69_sorted = outputs.windows(2).all(|w| to_satoshis(w[0].amount()) <= to_satoshis(w[1].amount()));
+        if outputs.len() < 2 || bip69_sorted {
+            return vec![None; outputs.len()]
+        }
+        let last = outputs.iter().map(|o| o.index()).max();
+        outputs.iter()
+            .map(|o| Some(if Some(o.index()) == last { 0.55 } else { 0.45 }))
+            .collect()
+    }
+}
+
+pub struct ChangeDetector {
+    heuristics: Vec<(Box<dyn ChangeHeuristic>, f64)>,
+    min_confidence: f64
+}
+
+impl Default for ChangeDetector {
+    fn default() -> Self {
+        ChangeDetector::new()
+    }
+}
+
+impl ChangeDetector {
+    pub fn new() -> Self {
+        let mut detector = ChangeDetector::empty();
+        detector
+            .add_heuristic(Box::new(AddressTypeHeuristic), 1.0)
+            .add_heuristic(Box::new(RoundNumberHeuristic::default()), 1.0)
+            .add_heuristic(Box::new(FreshAddressHeuristic), 1.5)
+            .add_heuristic(Box::new(OutputOrderHeuristic), 0.5);
+        detector
+    }
+
+    pub fn empty() -> Self {
+        ChangeDetector { heuristics: vec![], min_confidence: 0.6 }
+    }
+
+    pub fn add_heuristic(&mut self, heuristic: Box<dyn ChangeHeuristic>, weight: f64) -> &mut Self {
+        self.heuristics.push((heuristic, weight));
+        self
+    }
+
+    pub fn set_min_confidence(&mut self, min_confidence: f64) -> &mut Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    pub fn detect(&self, transaction: &Transaction, context: &ChangeContext) -> ChangeDetection {
+        let outputs: Vec<&TransactionAmount> = transaction.transaction_amounts().iter()
+            .filter(|a| a.is_output())
+            .collect();
+        let mut scored: Vec<OutputChangeScore> = outputs.iter()
+            .map(|o| OutputChangeScore {
+                index: o.index(),
+                address_hash: o.address_hash().clone(),
+                scores: vec![],
+                confidence: None
+            })
+            .collect();
+
+        // Coinbase and single output transactions have no change to find
+        if !transaction.is_coinbase() && outputs.len() > 1 {
+            for (heuristic, weight) in &self.heuristics {
+                let scores = heuristic.score(transaction, &outputs, context);
+                for (output, score) in scored.iter_mut().zip(scores) {
+                    if let Some(score) = score {
+                        output.scores.push(HeuristicScore { name: heuristic.name().to_string(), score, weight: *weight });
+                    }
+                }
+            }
+            for output in scored.iter_mut() {
+                let total_weight: f64 = output.scores.iter().map(|s| s.weight).sum();
+                if total_weight > 0.0 {
+                    let weighted: f64 = output.scores.iter().map(|s| s.score * s.weight).sum();
+                    output.confidence = Some(weighted / total_weight);
+                }
+            }
+        }
+
+        let mut best: Option<&OutputChangeScore> = None;
+        let mut tied = false;
+        for output in scored.iter().filter(|o| o.confidence.unwrap_or(0.0) >= self.min_confidence) {
+            match best {
+                Some(b) if b.confidence == output.confidence => tied = true,
+                Some(b) if b.confidence > output.confidence => {},
+                _ => {
+                    best = Some(output);
+                    tied = false;
+                }
+            }
+        }
+        let change_index = if tied { None } else { best.map(|b| b.index) };
+
+        ChangeDetection {
+            transaction_hash: transaction.hash().clone(),
+            change_index,
+            outputs: scored
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct HeuristicScore {
+    #[getset(get = "pub")]
+    name: String,
+
+    #[getset(get_copy = "pub")]
+    score: f64,
+
+    #[getset(get_copy = "pub")]
+    weight: f64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct OutputChangeScore {
+    #[getset(get_copy = "pub")]
+    index: i32,
+
+    #[getset(get = "pub")]
+    address_hash: String,
+
+    #[getset(get = "pub")]
+    scores: Vec<HeuristicScore>,
+
+    #[getset(get_copy = "pub")]
+    confidence: Option<f64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct ChangeDetection {
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    // Output index judged to be change, None when nothing was confident enough
+    #[getset(get_copy = "pub")]
+    change_index: Option<i32>,
+
+    #[getset(get = "pub")]
+    outputs: Vec<OutputChangeScore>
+}
+
+impl ChangeDetection {
+    pub fn change_output(&self) -> Option<&OutputChangeScore> {
+        let index = self.change_index?;
+        self.outputs.iter().find(|o| o.index == index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::change::*;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::transaction;
+
+    const P2WPKH: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+    const P2WPKH_2: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const P2PKH: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+
+    fn outputs_of(transaction: &Transaction) -> Vec<&TransactionAmount> {
+        transaction.transaction_amounts().iter().filter(|a| a.is_output()).collect()
+    }
+
+    #[test]
+    fn address_type_heuristic_test() {
+        let tx = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.0), (P2WPKH_2, 0.99)]);
+        let scores = AddressTypeHeuristic.score(&tx, &outputs_of(&tx), &ChangeContext::new());
+        assert_eq!(scores, vec![Some(0.1), Some(0.8)]);
+    }
+
+    #[test]
+    fn round_number_heuristic_test() {
+        let tx = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.5), (P2WPKH_2, 0.49871234)]);
+        let scores = RoundNumberHeuristic::default().score(&tx, &outputs_of(&tx), &ChangeContext::new());
+        assert_eq!(scores, vec![Some(0.2), Some(0.7)]);
+    }
+
+    #[test]
+    fn fresh_address_heuristic_test() {
+        let mut context = ChangeContext::new();
+        context.observe_transaction(&transaction(&[], &[(P2PKH, 1.0)]));
+
+        let tx = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.0), (P2WPKH_2, 0.99)]);
+        let scores = FreshAddressHeuristic.score(&tx, &outputs_of(&tx), &context);
+        assert_eq!(scores, vec![Some(0.15), Some(0.75)]);
+
+        let self_change = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.0), (P2WPKH, 0.99)]);
+        let scores = FreshAddressHeuristic.score(&self_change, &outputs_of(&self_change), &context);
+        assert_eq!(scores, vec![Some(0.05), Some(0.95)]);
+    }
+
+    #[test]
+    fn output_order_heuristic_test() {
+        let tx = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.5), (P2WPKH_2, 0.4)]);
+        assert_eq!(OutputOrderHeuristic.score(&tx, &outputs_of(&tx), &ChangeContext::new()), vec![Some(0.45), Some(0.55)]);
+
+        let sorted = transaction(&[(P2WPKH, 2.0)], &[(P2WPKH_2, 0.4), (P2PKH, 1.5)]);
+        assert_eq!(OutputOrderHeuristic.score(&sorted, &outputs_of(&sorted), &ChangeContext::new()), vec![None, None]);
+    }
+
+    #[test]
+    fn detector_combines_heuristics_test() {
+        let mut context = ChangeContext::new();
+        context.observe_transaction(&transaction(&[], &[(P2PKH, 1.0)]));
+        let tx = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.5), (P2WPKH_2, 0.49871234)]);
+
+        let detection = ChangeDetector::new().detect(&tx, &context);
+
+        assert_eq!(detection.change_index(), Some(1));
+        assert_eq!(detection.change_output().unwrap().address_hash(), &P2WPKH_2.to_string());
+        assert_eq!(detection.outputs()[1].scores().len(), 4);
+    }
+
+    #[test]
+    fn detector_without_heuristics_test() {
+        let tx = transaction(&[(P2WPKH, 2.0)], &[(P2PKH, 1.5), (P2WPKH_2, 0.49871234)]);
+        let detection = ChangeDetector::empty().detect(&tx, &ChangeContext::new());
+
+        assert_eq!(detection.change_index(), None);
+        assert_eq!(detection.outputs()[0].confidence(), None);
+    }
+}
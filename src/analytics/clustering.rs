@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::common::address::Address;
+use crate::common::block::Block;
+use crate::common::transaction::{Transaction, to_satoshis};
+
+// Groups addresses with the common-input-ownership heuristic: every address
+// spent from in the same transaction is assumed to belong to the same entity.
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct AddressClusterer {
+    indexes: HashMap<String, usize>,
+    hashes: Vec<String>,
+    parents: Vec<usize>,
+    ranks: Vec<u8>,
+    smallest: Vec<usize>, // index of the lexicographically smallest hash under each root
+
+    // Transactions matching the filter are never used to merge clusters
+    #[getset(get_copy = "pub")]
+    coinjoin_filter: Option<fn(&Transaction) -> bool>,
+
+    #[getset(get_copy = "pub")]
+    skipped_transactions: usize
+}
+
+impl Default for AddressClusterer {
+    fn default() -> Self {
+        AddressClusterer::new()
+    }
+}
+
+impl AddressClusterer {
+    pub fn new() -> Self {
+        AddressClusterer {
+            indexes: HashMap::new(),
+            hashes: vec![],
+            parents: vec![],
+            ranks: vec![],
+            smallest: vec![],
+            coinjoin_filter: Some(is_likely_coinjoin),
+            skipped_transactions: 0
+        }
+    }
+
+    pub fn set_coinjoin_filter(&mut self, filter: Option<fn(&Transaction) -> bool>) -> &mut Self {
+        self.coinjoin_filter = filter;
+        self
+    }
+
+    pub fn process_block(&mut self, block: &Block) -> &mut Self {
+        for transaction in block.transactions() {
+            self.process_transaction(transaction);
+        }
+        self
+    }
+
+    pub fn process_transaction(&mut self, transaction: &Transaction) -> &mut Self {
+        let mut inputs = vec![];
+        for amount in transaction.transaction_amounts() {
+            let index = self.index_of(amount.address_hash());
+            if amount.is_input() && !inputs.contains(&index) {
+                inputs.push(index);
+            }
+        }
+
+        if transaction.is_coinbase() || inputs.len() < 2 {
+            return self
+        }
+        if let Some(filter) = self.coinjoin_filter {
+            if filter(transaction) {
+                self.skipped_transactions += 1;
+                return self
+            }
+        }
+
+        for other in &inputs[1..] {
+            self.union(inputs[0], *other);
+        }
+        self
+    }
+
+    // Clusters are identified by their smallest address hash, so ids don't
+    // depend on the order transactions were processed in
+    pub fn cluster_id(&self, address_hash: &str) -> Option<&String> {
+        let index = *self.indexes.get(address_hash)?;
+        let root = self.find(index);
+        Some(&self.hashes[self.smallest[root]])
+    }
+
+    pub fn same_cluster(&self, a: &str, b: &str) -> bool {
+        match (self.indexes.get(a), self.indexes.get(b)) {
+            (Some(a), Some(b)) => self.find(*a) == self.find(*b),
+            _ => false
+        }
+    }
+
+    pub fn clusters(&self) -> Vec<Cluster> {
+        let mut clusters: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+        for (index, hash) in self.hashes.iter().enumerate() {
+            let id = &self.hashes[self.smallest[self.find(index)]];
+            clusters.entry(id).or_default().push(hash.clone());
+        }
+        clusters.into_iter()
+            .map(|(id, mut addresses)| {
+                addresses.sort();
+                Cluster { id: id.clone(), addresses }
+            })
+            .collect()
+    }
+
+    // Addresses the clusterer hasn't seen form their own single address cluster
+    pub fn cluster_balances(&self, addresses: &[Address]) -> Vec<ClusterBalance> {
+        let mut balances: BTreeMap<String, ClusterBalance> = BTreeMap::new();
+        let mut counted: HashSet<&String> = HashSet::new();
+        for address in addresses {
+            if !counted.insert(address.hash()) {
+                continue
+            }
+            let id = self.cluster_id(address.hash()).unwrap_or(address.hash()).clone();
+            let balance = balances.entry(id.clone()).or_insert(ClusterBalance {
+                id,
+                address_count: 0,
+                coin_total: 0.0
+            });
+            balance.address_count += 1;
+            balance.coin_total += address.coin_total();
+        }
+        balances.into_values().collect()
+    }
+
+    // Private Methods:
+    fn index_of(&mut self, address_hash: &str) -> usize {
+        if let Some(index) = self.indexes.get(address_hash) {
+            return *index
+        }
+        let index = self.hashes.len();
+        self.indexes.insert(address_hash.to_string(), index);
+        self.hashes.push(address_hash.to_string());
+        self.parents.push(index);
+        self.ranks.push(0);
+        self.smallest.push(index);
+        index
+    }
+
+    fn find(&self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            index = self.parents[index];
+        }
+        index
+    }
+
+    fn find_compress(&mut self, index: usize) -> usize {
+        let root = self.find(index);
+        let mut current = index;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find_compress(a), self.find_compress(b));
+        if a == b {
+            return
+        }
+        let (root, child) = if self.ranks[a] >= self.ranks[b] { (a, b) } else { (b, a) };
+        if self.ranks[root] == self.ranks[child] {
+            self.ranks[root] += 1;
+        }
+        self.parents[child] = root;
+        if self.hashes[self.smallest[child]] < self.hashes[self.smallest[root]] {
+            self.smallest[root] = self.smallest[child];
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters)]
+pub struct Cluster {
+    #[getset(get = "pub")]
+    id: String,
+
+    #[getset(get = "pub")]
+    addresses: Vec<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct ClusterBalance {
+    #[getset(get = "pub")]
+    id: String,
+
+    #[getset(get_copy = "pub")]
+    address_count: usize,
+
+    #[getset(get_copy = "pub")]
+    coin_total: f64
+}
+
+// Equal value outputs paid for by several distinct owners (Whirlpool, Wasabi, JoinMarket)
+pub fn is_likely_coinjoin(transaction: &Transaction) -> bool {
+    let amounts = transaction.transaction_amounts();
+    let input_addresses: HashSet<&String> = amounts.iter()
+        .filter(|a| a.is_input())
+        .map(|a| a.address_hash())
+        .collect();
+
+    let mut output_values: HashMap<i64, usize> = HashMap::new();
+    for output in amounts.iter().filter(|a| a.is_output()) {
+        *output_values.entry(to_satoshis(output.amount())).or_default() += 1;
+    }
+    let equal_outputs = output_values.values().copied().max().unwrap_or(0);
+
+    equal_outputs >= 3 && input_addresses.len() >= equal_outputs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::clustering::{AddressClusterer, is_likely_coinjoin};
+    use crate::common::address::Address;
+    use crate::test_fixtures::{transaction_at, DATE};
+
+    #[test]
+    fn multi_input_merges_test() {
+        let mut clusterer = AddressClusterer::new();
+        clusterer.process_transaction(&transaction_at("tx1", DATE, false, &[("c", 1.0), ("b", 1.0)], &[("x", 1.9)]));
+        clusterer.process_transaction(&transaction_at("tx2", DATE, false, &[("b", 1.0), ("d", 1.0)], &[("y", 1.9)]));
+
+        assert!(clusterer.same_cluster("c", "d"));
+        assert!(!clusterer.same_cluster("c", "x"));
+        assert_eq!(clusterer.cluster_id("d"), Some(&"b".to_string()));
+        assert_eq!(clusterer.cluster_id("x"), Some(&"x".to_string()));
+        assert_eq!(clusterer.clusters().len(), 3);
+    }
+
+    #[test]
+    fn cluster_ids_are_stable_test() {
+        let first = transaction_at("tx1", DATE, false, &[("c", 1.0), ("b", 1.0)], &[]);
+        let second = transaction_at("tx2", DATE, false, &[("a", 1.0), ("c", 1.0)], &[]);
+
+        let mut forward = AddressClusterer::new();
+        forward.process_transaction(&first).process_transaction(&second);
+        let mut backward = AddressClusterer::new();
+        backward.process_transaction(&second).process_transaction(&first);
+
+        assert_eq!(forward.cluster_id("b"), Some(&"a".to_string()));
+        assert_eq!(forward.clusters(), backward.clusters());
+    }
+
+    #[test]
+    fn coinjoin_filter_test() {
+        let coinjoin = transaction_at("mix", DATE, false, &[("a", 1.1), ("b", 1.2), ("c", 1.3)], &[("x", 1.0), ("y", 1.0), ("z", 1.0), ("b2", 0.2)]);
+        assert!(is_likely_coinjoin(&coinjoin));
+
+        let mut clusterer = AddressClusterer::new();
+        clusterer.process_transaction(&coinjoin);
+        assert!(!clusterer.same_cluster("a", "b"));
+        assert_eq!(clusterer.skipped_transactions(), 1);
+
+        clusterer.set_coinjoin_filter(None);
+        clusterer.process_transaction(&coinjoin);
+        assert!(clusterer.same_cluster("a", "c"));
+    }
+
+    #[test]
+    fn cluster_balances_test() {
+        let mut clusterer = AddressClusterer::new();
+        clusterer.process_transaction(&transaction_at("tx1", DATE, false, &[("a", 1.0), ("b", 1.0)], &[]));
+
+        let mut addresses = vec![];
+        for (hash, total) in [("a", 2.0), ("b", 3.0), ("z", 4.0)] {
+            let mut address = Address::new(hash.to_string());
+            address.set_coin_total(total);
+            addresses.push(address);
+        }
+
+        let balances = clusterer.cluster_balances(&addresses);
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[0].id(), &"a".to_string());
+        assert_eq!(balances[0].address_count(), 2);
+        assert_eq!(balances[0].coin_total(), 5.0);
+        assert_eq!(balances[1].coin_total(), 4.0);
+    }
+}
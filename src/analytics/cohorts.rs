@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::BTreeMap;
+
+use crate::common::address::Address;
+use crate::common::period::Period;
+
+// One cohort measured over one window, `offset` windows after the cohort's first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct CohortCell {
+    offset: usize,
+    window_start: i64,
+    active_count: i64,     // transacted during the window
+    holding_count: i64,    // still holding a balance at the window's end
+    churned_count: i64,    // held at the previous window's end, emptied by this one's
+    retained_balance: f64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct Cohort {
+    #[getset(get = "pub")]
+    label: String,
+
+    #[getset(get_copy = "pub")]
+    start: i64,
+
+    #[getset(get_copy = "pub")]
+    size: i64,
+
+    #[getset(get = "pub")]
+    cells: Vec<CohortCell>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CohortMetric {
+    ActiveCount,
+    ActiveShare, // active count over cohort size
+    HoldingCount,
+    ChurnedCount,
+    RetainedBalance,
+}
+
+// Rows are cohorts, columns window offsets; cells past the table's end are None
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct CohortMatrix {
+    #[getset(get_copy = "pub")]
+    metric: CohortMetric,
+
+    #[getset(get = "pub")]
+    rows: Vec<String>,
+
+    #[getset(get = "pub")]
+    columns: Vec<usize>,
+
+    #[getset(get = "pub")]
+    values: Vec<Vec<Option<f64>>>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct CohortTable {
+    #[getset(get_copy = "pub")]
+    period: Period,
+
+    #[getset(get_copy = "pub")]
+    end: i64,
+
+    #[getset(get = "pub")]
+    cohorts: Vec<Cohort>
+}
+
+impl CohortTable {
+    // Groups addresses by the period of their first_transaction and follows each group
+    // through every window up to the one containing `end`. Addresses that never
+    // transacted (first_transaction 0) are skipped.
+    pub fn build(addresses: &[Address], period: Period, end: i64) -> Self {
+        let mut groups: BTreeMap<i64, Vec<&Address>> = BTreeMap::new();
+        for address in addresses.iter().filter(|a| a.first_transaction() > 0 && a.first_transaction() <= end) {
+            groups.entry(period.start_of(address.first_transaction())).or_default().push(address);
+        }
+
+        let cohorts = groups.into_iter().map(|(start, members)| {
+            let mut cells = vec![];
+            let mut holding: Vec<bool> = vec![false; members.len()];
+            for (offset, window_start) in period.range(start, end).into_iter().enumerate() {
+                let window_end = period.next(window_start) - 1;
+                let mut cell = CohortCell { offset, window_start, ..CohortCell::default() };
+                for (i, address) in members.iter().enumerate() {
+                    if !address.history().range(window_start, window_end).is_empty() {
+                        cell.active_count += 1;
+                    }
+                    let balance = address.balance_at_time(window_end);
+                    let holds = balance > 0.0;
+                    if holds {
+                        cell.holding_count += 1;
+                        cell.retained_balance += balance;
+                    } else if holding[i] {
+                        cell.churned_count += 1;
+                    }
+                    holding[i] = holds;
+                }
+                cells.push(cell);
+            }
+            Cohort { label: period.label(start), start, size: members.len() as i64, cells }
+        }).collect();
+
+        CohortTable { period, end, cohorts }
+    }
+
+    pub fn cohort(&self, label: &str) -> Option<&Cohort> {
+        self.cohorts.iter().find(|c| c.label == label)
+    }
+
+    pub fn matrix(&self, metric: CohortMetric) -> CohortMatrix {
+        let width = self.cohorts.iter().map(|c| c.cells.len()).max().unwrap_or(0);
+        let values = self.cohorts.iter().map(|cohort| {
+            (0..width).map(|offset| cohort.cells.get(offset).map(|cell| match metric {
+                CohortMetric::ActiveCount => cell.active_count as f64,
+                CohortMetric::ActiveShare => cell.active_count as f64 / cohort.size as f64,
+                CohortMetric::HoldingCount => cell.holding_count as f64,
+                CohortMetric::ChurnedCount => cell.churned_count as f64,
+                CohortMetric::RetainedBalance => cell.retained_balance,
+            })).collect()
+        }).collect();
+
+        CohortMatrix {
+            metric,
+            rows: self.cohorts.iter().map(|c| c.label.clone()).collect(),
+            columns: (0..width).collect(),
+            values
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::cohorts::*;
+    use crate::common::address::Address;
+    use crate::common::period::Period;
+    use crate::common::transaction::TransactionAmount;
+    use crate::test_fixtures::{address, input};
+
+    const JAN: i64 = 1672531200; // 2023-01-01
+    const FEB: i64 = 1675209600;
+    const MAR: i64 = 1677628800;
+
+    fn output(hash: &str, amount: f64, date: i64) -> TransactionAmount {
+        TransactionAmount::new(amount, hash.to_string(), "hashy".to_string(), date, 0)
+    }
+
+    fn addresses() -> Vec<Address> {
+        vec![
+            address("alice", vec![output("alice", 1.0, JAN + 10), input("alice", "hashy", 1.0, FEB + 10)]),
+            address("bob", vec![output("bob", 2.0, JAN + 20)]),
+            address("carol", vec![output("carol", 5.0, FEB + 30), output("carol", 1.0, MAR + 30)]),
+            Address::new("never".to_string()),
+        ]
+    }
+
+    #[test]
+    fn build_test() {
+        let table = CohortTable::build(&addresses(), Period::Month, MAR + 100);
+
+        assert_eq!(table.cohorts().len(), 2);
+        let january = table.cohort("2023-01").unwrap();
+        assert_eq!(january.size(), 2);
+        assert_eq!(january.cells().len(), 3);
+        assert_eq!(january.cells()[0].active_count(), 2);
+        assert_eq!(january.cells()[0].retained_balance(), 3.0);
+        assert_eq!(january.cells()[1].active_count(), 1);
+        assert_eq!(january.cells()[1].holding_count(), 1);
+        assert_eq!(january.cells()[1].churned_count(), 1);
+        assert_eq!(january.cells()[2].churned_count(), 0);
+
+        let february = table.cohort("2023-02").unwrap();
+        assert_eq!(february.cells()[1].retained_balance(), 6.0);
+    }
+
+    #[test]
+    fn matrix_test() {
+        let table = CohortTable::build(&addresses(), Period::Month, MAR + 100);
+        let matrix = table.matrix(CohortMetric::ActiveShare);
+
+        assert_eq!(matrix.rows(), &vec!["2023-01".to_string(), "2023-02".to_string()]);
+        assert_eq!(matrix.columns(), &vec![0, 1, 2]);
+        assert_eq!(matrix.values()[0], vec![Some(1.0), Some(0.5), Some(0.0)]);
+        assert_eq!(matrix.values()[1], vec![Some(1.0), Some(1.0), None]);
+
+        let json = serde_json::to_value(&matrix).unwrap();
+        assert_eq!(json["metric"], "active_share");
+        assert!(json["values"][1][2].is_null());
+    }
+}
@@ -0,0 +1,290 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use easy_error::bail;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::error::Error;
+
+use crate::analytics::clustering::ClusterBalance;
+use crate::common::address::Address;
+
+// Upper bounds of each balance band in coins, the last band is open ended.
+// Stored as the plain list of bounds, deserializing goes through BalanceBands::new
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "Vec<f64>", into = "Vec<f64>")]
+pub struct BalanceBands {
+    bounds: Vec<f64>
+}
+
+impl Default for BalanceBands {
+    fn default() -> Self {
+        BalanceBands { bounds: vec![0.001, 0.01, 0.1, 1.0, 10.0, 100.0, 1000.0, 10000.0] }
+    }
+}
+
+impl BalanceBands {
+    pub fn new(bounds: Vec<f64>) -> Result<Self, Box<dyn Error>> {
+        if bounds.iter().any(|bound| *bound <= 0.0) || bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            bail!("Balance band bounds must be positive and ascending: {:?}", bounds);
+        }
+        Ok(BalanceBands { bounds })
+    }
+
+    pub fn bounds(&self) -> &Vec<f64> {
+        &self.bounds
+    }
+
+    pub fn band_index(&self, balance: f64) -> usize {
+        self.bounds.partition_point(|bound| *bound <= balance)
+    }
+
+    fn empty_bands(&self) -> Vec<BalanceBand> {
+        (0..=self.bounds.len()).map(|i| {
+            let min = if i == 0 { 0.0 } else { self.bounds[i - 1] };
+            let max = self.bounds.get(i).copied();
+            let label = match (i, max) {
+                (0, Some(max)) => format!("<{}", max),
+                (_, Some(max)) => format!("{}-{}", min, max),
+                (_, None) => format!(">{}", min),
+            };
+            BalanceBand { label, min, max, address_count: 0, supply: 0.0 }
+        }).collect()
+    }
+}
+
+impl TryFrom<Vec<f64>> for BalanceBands {
+    type Error = Box<dyn Error>;
+
+    fn try_from(bounds: Vec<f64>) -> Result<Self, Self::Error> {
+        BalanceBands::new(bounds)
+    }
+}
+
+impl From<BalanceBands> for Vec<f64> {
+    fn from(bands: BalanceBands) -> Self {
+        bands.bounds
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct BalanceBand {
+    #[getset(get = "pub")]
+    label: String,
+
+    #[getset(get_copy = "pub")]
+    min: f64,
+
+    #[getset(get_copy = "pub")]
+    max: Option<f64>,
+
+    #[getset(get_copy = "pub")]
+    address_count: i64,
+
+    #[getset(get_copy = "pub")]
+    supply: f64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct RichListEntry {
+    #[getset(get_copy = "pub")]
+    rank: usize,
+
+    // Address hash or cluster id
+    #[getset(get = "pub")]
+    hash: String,
+
+    #[getset(get_copy = "pub")]
+    coin_total: f64,
+
+    #[getset(get_copy = "pub")]
+    share: f64 // of the total supply
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, Getters, CopyGetters)]
+pub struct SupplyDistribution {
+    #[getset(get_copy = "pub")]
+    address_count: i64,
+
+    #[getset(get_copy = "pub")]
+    total_supply: f64,
+
+    #[getset(get = "pub")]
+    rich_list: Vec<RichListEntry>,
+
+    #[getset(get = "pub")]
+    bands: Vec<BalanceBand>,
+
+    #[getset(get_copy = "pub")]
+    gini_coefficient: f64,
+
+    // Fewest holders that together control more than half the supply
+    #[getset(get_copy = "pub")]
+    nakamoto_coefficient: i64
+}
+
+impl SupplyDistribution {
+    pub fn from_addresses(addresses: &[Address], bands: &BalanceBands, top: usize) -> Self {
+        SupplyDistribution::from_balances(addresses.iter().map(|a| (a.hash().as_str(), a.coin_total())), bands, top)
+    }
+
+    pub fn from_clusters(clusters: &[ClusterBalance], bands: &BalanceBands, top: usize) -> Self {
+        SupplyDistribution::from_balances(clusters.iter().map(|c| (c.id().as_str(), c.coin_total())), bands, top)
+    }
+
+    // Zero and negative balances are left out of every measure.
+    // Only the top entries are kept in a heap so the rich list stays O(n log top).
+    pub fn from_balances<'a>(balances: impl Iterator<Item = (&'a str, f64)>, bands: &BalanceBands, top: usize) -> Self {
+        let mut band_totals = bands.empty_bands();
+        let mut heap: BinaryHeap<Reverse<Holder>> = BinaryHeap::with_capacity(top + 1);
+        let mut values = vec![];
+
+        for (hash, coin_total) in balances.filter(|(_, total)| *total > 0.0) {
+            let band = &mut band_totals[bands.band_index(coin_total)];
+            band.address_count += 1;
+            band.supply += coin_total;
+            values.push(coin_total);
+
+            if top > 0 {
+                heap.push(Reverse(Holder { coin_total, hash }));
+                if heap.len() > top {
+                    heap.pop();
+                }
+            }
+        }
+
+        values.sort_unstable_by(|a, b| a.total_cmp(b));
+        let total_supply: f64 = values.iter().sum();
+        let rich_list = heap.into_sorted_vec().into_iter().enumerate()
+            .map(|(i, Reverse(holder))| RichListEntry {
+                rank: i + 1,
+                hash: holder.hash.to_string(),
+                coin_total: holder.coin_total,
+                share: holder.coin_total / total_supply
+            })
+            .collect();
+
+        SupplyDistribution {
+            address_count: values.len() as i64,
+            total_supply,
+            rich_list,
+            bands: band_totals,
+            gini_coefficient: gini(&values),
+            nakamoto_coefficient: nakamoto(&values, 0.5)
+        }
+    }
+
+    // Share of the total supply held by the rich list
+    pub fn top_share(&self) -> f64 {
+        if self.total_supply <= 0.0 {
+            return 0.0
+        }
+        self.rich_list.iter().map(|entry| entry.coin_total).sum::<f64>() / self.total_supply
+    }
+}
+
+// Gini of ascending sorted balances, 0 is perfectly equal and 1 one holder owns everything
+pub fn gini(sorted: &[f64]) -> f64 {
+    let n = sorted.len() as f64;
+    let total: f64 = sorted.iter().sum();
+    if sorted.is_empty() || total <= 0.0 {
+        return 0.0
+    }
+    let weighted: f64 = sorted.iter().enumerate().map(|(i, value)| (i + 1) as f64 * value).sum();
+    (2.0 * weighted) / (n * total) - (n + 1.0) / n
+}
+
+// Number of the largest ascending sorted balances needed to exceed `threshold` of the total
+pub fn nakamoto(sorted: &[f64], threshold: f64) -> i64 {
+    let target = sorted.iter().sum::<f64>() * threshold;
+    let mut held = 0.0;
+    for (count, value) in sorted.iter().rev().enumerate() {
+        held += value;
+        if held > target {
+            return count as i64 + 1
+        }
+    }
+    0
+}
+
+struct Holder<'a> {
+    coin_total: f64,
+    hash: &'a str
+}
+
+impl Ord for Holder<'_> {
+    // Ties go to the smaller hash so the rich list is deterministic
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.coin_total.total_cmp(&other.coin_total).then_with(|| other.hash.cmp(self.hash))
+    }
+}
+
+impl PartialOrd for Holder<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Holder<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Holder<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::distribution::*;
+    use crate::common::address::Address;
+
+    fn addresses(totals: &[(&str, f64)]) -> Vec<Address> {
+        totals.iter().map(|(hash, total)| {
+            let mut address = Address::new(hash.to_string());
+            address.set_coin_total(*total);
+            address
+        }).collect()
+    }
+
+    #[test]
+    fn rich_list_and_bands_test() {
+        let addresses = addresses(&[("a", 0.0005), ("b", 50.0), ("c", 20000.0), ("d", 50.0), ("e", 0.0), ("f", 3.0)]);
+        let distribution = SupplyDistribution::from_addresses(&addresses, &BalanceBands::default(), 3);
+
+        assert_eq!(distribution.address_count(), 5);
+        let ranked: Vec<&str> = distribution.rich_list().iter().map(|e| e.hash().as_str()).collect();
+        assert_eq!(ranked, vec!["c", "b", "d"]);
+        assert_eq!(distribution.rich_list()[0].rank(), 1);
+
+        let bands = distribution.bands();
+        assert_eq!(bands.len(), 9);
+        assert_eq!(bands[0].label(), &"<0.001".to_string());
+        assert_eq!(bands[0].address_count(), 1);
+        assert_eq!(bands[5].label(), &"10-100".to_string());
+        assert_eq!(bands[5].supply(), 100.0);
+        assert_eq!(bands[8].label(), &">10000".to_string());
+        assert_eq!(bands[8].address_count(), 1);
+        assert_eq!(distribution.nakamoto_coefficient(), 1);
+        assert_eq!(distribution.top_share(), 20100.0 / 20103.0005);
+    }
+
+    #[test]
+    fn concentration_test() {
+        assert_eq!(gini(&[5.0, 5.0, 5.0, 5.0]), 0.0);
+        assert_eq!(gini(&[0.0, 0.0, 0.0, 10.0]), 0.75);
+        assert_eq!(nakamoto(&[1.0, 1.0, 1.0, 1.0], 0.5), 3);
+        assert_eq!(nakamoto(&[1.0, 2.0, 3.0, 4.0], 0.5), 2);
+        assert_eq!(nakamoto(&[], 0.5), 0);
+    }
+
+    #[test]
+    fn invalid_bands_test() {
+        assert!(BalanceBands::new(vec![1.0, 0.5]).is_err());
+        assert!(BalanceBands::new(vec![0.0, 1.0]).is_err());
+        assert_eq!(BalanceBands::new(vec![1.0]).unwrap().band_index(1.0), 1);
+
+        assert!(serde_json::from_str::<BalanceBands>("[1.0, 0.5]").is_err());
+        let bands: BalanceBands = serde_json::from_str("[0.5, 1.0]").unwrap();
+        assert_eq!(bands.bounds(), &vec![0.5, 1.0]);
+        assert_eq!(serde_json::to_string(&bands).unwrap(), "[0.5,1.0]");
+    }
+}
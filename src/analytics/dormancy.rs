@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use getset::CopyGetters;
+
+use crate::analytics::index::TransactionIndex;
+use crate::common::transaction::Transaction;
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+// Coin days destroyed by one transaction's inputs: value times days since the
+// consumed output was created. Inputs whose output isn't indexed are counted as unresolved.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct CoinDays {
+    coin_days_destroyed: f64,
+    spent_volume: f64,
+    resolved_spends: i64,
+    unresolved_spends: i64
+}
+
+impl CoinDays {
+    pub fn for_transaction(index: &TransactionIndex, transaction: &Transaction) -> Self {
+        let mut coin_days = CoinDays::default();
+        for input in transaction.transaction_amounts().iter().filter(|a| a.is_input()) {
+            match index.spent_output(input) {
+                Some(output) => {
+                    let age = (transaction.date() - output.date()).max(0) as f64 / SECONDS_PER_DAY;
+                    coin_days.coin_days_destroyed += output.amount() * age;
+                    coin_days.spent_volume += output.amount();
+                    coin_days.resolved_spends += 1;
+                },
+                None => coin_days.unresolved_spends += 1,
+            }
+        }
+        coin_days
+    }
+
+    fn add(&mut self, other: &CoinDays) {
+        self.coin_days_destroyed += other.coin_days_destroyed;
+        self.spent_volume += other.spent_volume;
+        self.resolved_spends += other.resolved_spends;
+        self.unresolved_spends += other.unresolved_spends;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct DormancyStats {
+    coin_days_destroyed: f64,
+    spent_volume: f64,
+    average_dormancy: f64, // coin days destroyed per coin spent
+    binary_cdd: i64,       // 1 when the window destroyed more than the historical average
+    liveliness: f64,       // cumulative destroyed / cumulative created coin days
+    resolved_spends: i64,
+    unresolved_spends: i64
+}
+
+impl DormancyStats {
+    // `historical_cdd` holds coin days destroyed by earlier windows of the same length
+    pub fn for_window(index: &TransactionIndex, date_start: i64, date_end: i64, historical_cdd: &[f64]) -> Self {
+        let mut window = CoinDays::default();
+        for transaction in index.transactions().filter(|t| t.date() >= date_start && t.date() <= date_end) {
+            window.add(&CoinDays::for_transaction(index, transaction));
+        }
+
+        let average_dormancy = if window.spent_volume > 0.0 {
+            window.coin_days_destroyed / window.spent_volume
+        } else {
+            0.0
+        };
+        let binary_cdd = if historical_cdd.is_empty() {
+            0
+        } else {
+            let average = historical_cdd.iter().sum::<f64>() / historical_cdd.len() as f64;
+            (window.coin_days_destroyed > average) as i64
+        };
+
+        DormancyStats {
+            coin_days_destroyed: window.coin_days_destroyed,
+            spent_volume: window.spent_volume,
+            average_dormancy,
+            binary_cdd,
+            liveliness: liveliness(index, date_end),
+            resolved_spends: window.resolved_spends,
+            unresolved_spends: window.unresolved_spends
+        }
+    }
+}
+
+// Share of all coin days ever accumulated (up to `time`) that have since been destroyed
+pub fn liveliness(index: &TransactionIndex, time: i64) -> f64 {
+    let mut created = 0.0;
+    let mut destroyed = 0.0;
+    for transaction in index.transactions() {
+        for output in transaction.transaction_amounts().iter().filter(|a| a.is_output() && a.date() <= time) {
+            let spent = index.spender(transaction.hash(), output.index())
+                .map(|spender| spender.date())
+                .filter(|date| *date <= time);
+            let until = spent.unwrap_or(time);
+            let coin_days = output.amount() * (until - output.date()).max(0) as f64 / SECONDS_PER_DAY;
+            created += coin_days;
+            if spent.is_some() {
+                destroyed += coin_days;
+            }
+        }
+    }
+    if created > 0.0 { destroyed / created } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::dormancy::*;
+    use crate::analytics::index::TransactionIndex;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::spend_of;
+
+    const DAY: i64 = 86400;
+
+    // Two outputs created on day 0, one spent on day 10 and the other never spent
+    fn index() -> TransactionIndex {
+        let mut funding = Transaction::new("funding".to_string(), false, 0, "blocky_hash".to_string(), 1);
+        funding.set_transaction_amounts(vec![
+            TransactionAmount::new(2.0, "alice".to_string(), "funding".to_string(), 0, 0),
+            TransactionAmount::new(3.0, "bob".to_string(), "funding".to_string(), 0, 1),
+        ]);
+        let mut spend = Transaction::new("spend".to_string(), false, 10 * DAY, "blocky_hash".to_string(), 2);
+        spend.set_transaction_amounts(vec![
+            spend_of("funding", 0, "alice", "spend", 2.0, 10 * DAY),
+            spend_of("unindexed", 0, "carol", "spend", 1.0, 10 * DAY),
+            TransactionAmount::new(2.9, "dave".to_string(), "spend".to_string(), 10 * DAY, 0)]);
+
+        let mut index = TransactionIndex::new();
+        index.insert_transaction(funding).insert_transaction(spend);
+        index
+    }
+
+    #[test]
+    fn coin_days_for_transaction_test() {
+        let index = index();
+        let coin_days = CoinDays::for_transaction(&index, index.get("spend").unwrap());
+
+        assert_eq!(coin_days.coin_days_destroyed(), 20.0);
+        assert_eq!(coin_days.spent_volume(), 2.0);
+        assert_eq!(coin_days.resolved_spends(), 1);
+        assert_eq!(coin_days.unresolved_spends(), 1);
+    }
+
+    #[test]
+    fn window_stats_test() {
+        let index = index();
+        let stats = DormancyStats::for_window(&index, 5 * DAY, 10 * DAY, &[5.0, 10.0]);
+
+        assert_eq!(stats.coin_days_destroyed(), 20.0);
+        assert_eq!(stats.average_dormancy(), 10.0);
+        assert_eq!(stats.binary_cdd(), 1);
+        // created: alice 2 * 10 + bob 3 * 10 = 50, destroyed 20
+        assert_eq!(stats.liveliness(), 0.4);
+
+        let quiet = DormancyStats::for_window(&index, 0, 5 * DAY, &[5.0]);
+        assert_eq!(quiet.coin_days_destroyed(), 0.0);
+        assert_eq!(quiet.binary_cdd(), 0);
+        assert_eq!(quiet.liveliness(), 0.0);
+    }
+}
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::BTreeMap;
+
+use crate::common::address::Address;
+use crate::common::block::Block;
+use crate::common::transaction::Transaction;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Address,
+    Transaction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct GraphNode {
+    #[getset(get = "pub")]
+    id: String,
+
+    #[getset(get_copy = "pub")]
+    kind: NodeKind,
+
+    #[getset(get = "pub")]
+    label: String,
+
+    // Address balance when the Address was supplied
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coin_total: Option<f64>
+}
+
+// Inputs point address -> transaction, outputs transaction -> address
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct GraphEdge {
+    #[getset(get = "pub")]
+    source: String,
+
+    #[getset(get = "pub")]
+    target: String,
+
+    #[getset(get_copy = "pub")]
+    weight: f64,
+
+    #[getset(get_copy = "pub")]
+    date: i64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct GraphFilter {
+    pub min_value: f64,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+}
+
+impl GraphFilter {
+    fn includes_time(&self, date: i64) -> bool {
+        self.start_time.map(|start| date >= start).unwrap_or(true)
+            && self.end_time.map(|end| date <= end).unwrap_or(true)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TransactionGraph {
+    nodes: BTreeMap<String, GraphNode>,
+    edges: Vec<GraphEdge>
+}
+
+// networkx style node-link document
+#[derive(Serialize)]
+struct NodeLink<'a> {
+    directed: bool,
+    multigraph: bool,
+    nodes: Vec<&'a GraphNode>,
+    links: &'a Vec<GraphEdge>
+}
+
+impl TransactionGraph {
+    pub fn new() -> Self {
+        TransactionGraph::default()
+    }
+
+    pub fn from_blocks(blocks: &[Block], filter: &GraphFilter) -> Self {
+        let mut graph = TransactionGraph::new();
+        for block in blocks {
+            for transaction in block.transactions() {
+                graph.add_transaction(transaction, filter);
+            }
+        }
+        graph
+    }
+
+    pub fn add_transaction(&mut self, transaction: &Transaction, filter: &GraphFilter) -> &mut Self {
+        if !filter.includes_time(transaction.date()) {
+            return self
+        }
+        let transaction_id = format!("tx:{}", transaction.hash());
+        for amount in transaction.transaction_amounts() {
+            let weight = amount.amount().abs();
+            if weight < filter.min_value {
+                continue
+            }
+            let address_id = format!("addr:{}", amount.address_hash());
+            self.add_node(&address_id, NodeKind::Address, amount.address_hash());
+            self.add_node(&transaction_id, NodeKind::Transaction, transaction.hash());
+
+            let (source, target) = if amount.is_input() {
+                (address_id, transaction_id.clone())
+            } else {
+                (transaction_id.clone(), address_id)
+            };
+            self.edges.push(GraphEdge { source, target, weight, date: amount.date() });
+        }
+        self
+    }
+
+    // Attaches balances to address nodes already in the graph
+    pub fn add_addresses(&mut self, addresses: &[Address]) -> &mut Self {
+        for address in addresses {
+            if let Some(node) = self.nodes.get_mut(&format!("addr:{}", address.hash())) {
+                node.coin_total = Some(address.coin_total());
+            }
+        }
+        self
+    }
+
+    pub fn nodes(&self) -> Vec<&GraphNode> {
+        self.nodes.values().collect()
+    }
+
+    pub fn edges(&self) -> &Vec<GraphEdge> {
+        &self.edges
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph transactions {\n");
+        for node in self.nodes.values() {
+            let shape = match node.kind {
+                NodeKind::Address => "ellipse",
+                NodeKind::Transaction => "box",
+            };
+            dot.push_str(&format!("  \"{}\" [label=\"{}\", shape={}];\n", escape_dot(&node.id), escape_dot(&node.label), shape));
+        }
+        // Graphviz only takes integer edge weights, so the amount goes in the label and
+        // the line width instead
+        for edge in &self.edges {
+            let penwidth = 1.0 + (1.0 + edge.weight).log10();
+            dot.push_str(&format!("  \"{}\" -> \"{}\" [label=\"{}\", penwidth={:.2}];\n", escape_dot(&edge.source), escape_dot(&edge.target), edge.weight, penwidth));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        xml.push_str("  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"coin_total\" for=\"node\" attr.name=\"coin_total\" attr.type=\"double\"/>\n");
+        xml.push_str("  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"double\"/>\n");
+        xml.push_str("  <key id=\"date\" for=\"edge\" attr.name=\"date\" attr.type=\"long\"/>\n");
+        xml.push_str("  <graph id=\"transactions\" edgedefault=\"directed\">\n");
+        for node in self.nodes.values() {
+            let kind = match node.kind {
+                NodeKind::Address => "address",
+                NodeKind::Transaction => "transaction",
+            };
+            xml.push_str(&format!("    <node id=\"{}\">\n", escape_xml(&node.id)));
+            xml.push_str(&format!("      <data key=\"kind\">{}</data>\n", kind));
+            xml.push_str(&format!("      <data key=\"label\">{}</data>\n", escape_xml(&node.label)));
+            if let Some(coin_total) = node.coin_total {
+                xml.push_str(&format!("      <data key=\"coin_total\">{}</data>\n", coin_total));
+            }
+            xml.push_str("    </node>\n");
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            xml.push_str(&format!("    <edge id=\"e{}\" source=\"{}\" target=\"{}\">\n", i, escape_xml(&edge.source), escape_xml(&edge.target)));
+            xml.push_str(&format!("      <data key=\"weight\">{}</data>\n", edge.weight));
+            xml.push_str(&format!("      <data key=\"date\">{}</data>\n", edge.date));
+            xml.push_str("    </edge>\n");
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+
+    pub fn to_json(&self) -> String {
+        let document = NodeLink {
+            directed: true,
+            multigraph: true,
+            nodes: self.nodes(),
+            links: &self.edges
+        };
+        serde_json::to_string(&document).unwrap()
+    }
+
+    // Private Methods:
+    fn add_node(&mut self, id: &str, kind: NodeKind, label: &str) {
+        self.nodes.entry(id.to_string()).or_insert_with(|| GraphNode {
+            id: id.to_string(),
+            kind,
+            label: label.to_string(),
+            coin_total: None
+        });
+    }
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::export::{GraphFilter, NodeKind, TransactionGraph};
+    use crate::common::address::Address;
+    use crate::common::transaction::Transaction;
+    use crate::test_fixtures::transaction_at;
+
+    fn transaction(hash: &str, date: i64) -> Transaction {
+        transaction_at(hash, date, false, &[("alice", 2.0)], &[("bob", 1.5), ("dust\"y", 0.001)])
+    }
+
+    #[test]
+    fn build_graph_test() {
+        let mut graph = TransactionGraph::new();
+        graph.add_transaction(&transaction("tx1", 100), &GraphFilter::default());
+
+        assert_eq!(graph.nodes().len(), 4);
+        assert_eq!(graph.edges().len(), 3);
+        assert_eq!(graph.edges()[0].source(), &"addr:alice".to_string());
+        assert_eq!(graph.edges()[0].target(), &"tx:tx1".to_string());
+        assert_eq!(graph.edges()[0].weight(), 2.0);
+    }
+
+    #[test]
+    fn filters_test() {
+        let filter = GraphFilter { min_value: 0.01, start_time: Some(50), end_time: Some(150) };
+        let mut graph = TransactionGraph::new();
+        graph.add_transaction(&transaction("tx1", 100), &filter);
+        graph.add_transaction(&transaction("tx2", 200), &filter);
+
+        assert_eq!(graph.edges().len(), 2);
+        assert!(graph.nodes().iter().all(|n| n.label() != "dust\"y" && n.label() != "tx2"));
+    }
+
+    #[test]
+    fn export_formats_test() {
+        let mut graph = TransactionGraph::new();
+        graph.add_transaction(&transaction("tx1", 100), &GraphFilter::default());
+        let mut bob = Address::new("bob".to_string());
+        bob.set_coin_total(1.5);
+        graph.add_addresses(&[bob]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph transactions {"));
+        assert!(dot.contains("\"addr:alice\" -> \"tx:tx1\" [label=\"2\", penwidth=1.48];"));
+        assert!(dot.contains("\"tx:tx1\" -> \"addr:bob\" [label=\"1.5\", penwidth=1.40];"));
+        assert!(!dot.contains("weight="));
+        assert!(dot.contains("dust\\\"y"));
+
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains("<node id=\"addr:dust&quot;y\">"));
+        assert!(graphml.contains("<data key=\"coin_total\">1.5</data>"));
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 4);
+        assert_eq!(json["links"][1]["source"], "tx:tx1");
+        assert_eq!(graph.nodes()[0].kind(), NodeKind::Address);
+    }
+}
@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use easy_error::bail;
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::analytics::index::TransactionIndex;
+use crate::common::block::Block;
+use crate::common::blockchain::ChainStats;
+use crate::common::transaction::{to_satoshis, SATOSHIS_PER_COIN};
+
+const DAY: i64 = 86400;
+const YEAR: i64 = 365 * DAY;
+
+// Outputs whose age is in [min_age, max_age) seconds, max_age None is open ended
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct AgeBand {
+    #[getset(get = "pub")]
+    label: String,
+
+    #[getset(get_copy = "pub")]
+    min_age: i64,
+
+    #[getset(get_copy = "pub")]
+    #[serde(default)]
+    max_age: Option<i64>
+}
+
+impl AgeBand {
+    pub fn new(label: String, min_age: i64, max_age: Option<i64>) -> Self {
+        AgeBand { label, min_age, max_age }
+    }
+
+    pub fn contains(&self, age: i64) -> bool {
+        age >= self.min_age && self.max_age.map(|max| age < max).unwrap_or(true)
+    }
+}
+
+// Stored as the plain list of bands, deserializing goes through AgeBands::new
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "Vec<AgeBand>", into = "Vec<AgeBand>")]
+pub struct AgeBands {
+    bands: Vec<AgeBand>
+}
+
+impl Default for AgeBands {
+    fn default() -> Self {
+        let bounds = [
+            ("<1d", 0, Some(DAY)),
+            ("1d-1w", DAY, Some(7 * DAY)),
+            ("1w-1m", 7 * DAY, Some(30 * DAY)),
+            ("1m-3m", 30 * DAY, Some(90 * DAY)),
+            ("3m-6m", 90 * DAY, Some(180 * DAY)),
+            ("6m-1y", 180 * DAY, Some(YEAR)),
+            ("1y-2y", YEAR, Some(2 * YEAR)),
+            ("2y-3y", 2 * YEAR, Some(3 * YEAR)),
+            ("3y-5y", 3 * YEAR, Some(5 * YEAR)),
+            ("5y-7y", 5 * YEAR, Some(7 * YEAR)),
+            ("7y-10y", 7 * YEAR, Some(10 * YEAR)),
+            (">10y", 10 * YEAR, None),
+        ];
+        AgeBands {
+            bands: bounds.iter().map(|(label, min, max)| AgeBand::new(label.to_string(), *min, *max)).collect()
+        }
+    }
+}
+
+impl AgeBands {
+    // Bands must start at zero, be sorted and leave no gaps so every output lands in exactly one
+    pub fn new(bands: Vec<AgeBand>) -> Result<Self, Box<dyn Error>> {
+        if bands.is_empty() || bands[0].min_age != 0 {
+            bail!("Age bands must start at 0");
+        }
+        for pair in bands.windows(2) {
+            if pair[0].max_age != Some(pair[1].min_age) {
+                bail!("Age band {} does not end where {} starts", pair[0].label, pair[1].label);
+            }
+        }
+        if bands[bands.len() - 1].max_age.is_some() {
+            bail!("The last age band must be open ended");
+        }
+        Ok(AgeBands { bands })
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(raw)?)
+    }
+
+    pub fn bands(&self) -> &Vec<AgeBand> {
+        &self.bands
+    }
+
+    pub fn band_index(&self, age: i64) -> usize {
+        self.bands.iter().position(|band| band.contains(age.max(0))).unwrap_or(self.bands.len() - 1)
+    }
+}
+
+impl TryFrom<Vec<AgeBand>> for AgeBands {
+    type Error = Box<dyn Error>;
+
+    fn try_from(bands: Vec<AgeBand>) -> Result<Self, Self::Error> {
+        AgeBands::new(bands)
+    }
+}
+
+impl From<AgeBands> for Vec<AgeBand> {
+    fn from(bands: AgeBands) -> Self {
+        bands.bands
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct BandSupply {
+    #[getset(get = "pub")]
+    label: String,
+
+    #[getset(get_copy = "pub")]
+    supply: f64,
+
+    #[getset(get_copy = "pub")]
+    share: f64 // of the snapshot total, 0 to 1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct HodlSnapshot {
+    #[getset(get_copy = "pub")]
+    block_height: i64,
+
+    #[getset(get_copy = "pub")]
+    date: i64,
+
+    #[getset(get_copy = "pub")]
+    total: f64,
+
+    #[getset(get = "pub")]
+    bands: Vec<BandSupply>
+}
+
+impl HodlSnapshot {
+    // Unspent outputs as of `block_height`, aged against `date` (normally that block's time)
+    pub fn at(index: &TransactionIndex, bands: &AgeBands, block_height: i64, date: i64) -> Self {
+        let mut unspent = vec![];
+        for transaction in index.transactions().filter(|t| t.block_height() <= block_height) {
+            for output in transaction.transaction_amounts().iter().filter(|a| a.is_output()) {
+                let spent = index.spender(transaction.hash(), output.index())
+                    .map(|spender| spender.block_height() <= block_height)
+                    .unwrap_or(false);
+                if !spent {
+                    unspent.push((output.date(), to_satoshis(output.amount())));
+                }
+            }
+        }
+        HodlSnapshot::from_unspent(bands, block_height, date, unspent.into_iter())
+    }
+
+    // (date, satoshis) of every unspent output
+    fn from_unspent(bands: &AgeBands, block_height: i64, date: i64, unspent: impl Iterator<Item = (i64, i64)>) -> Self {
+        let mut supply = vec![0; bands.bands.len()];
+        for (output_date, satoshis) in unspent {
+            supply[bands.band_index(date - output_date)] += satoshis;
+        }
+
+        // Summed in satoshis so the total matches the active supply exactly
+        let total: i64 = supply.iter().sum();
+        HodlSnapshot {
+            block_height,
+            date,
+            total: from_satoshis(total),
+            bands: bands.bands.iter().zip(supply).map(|(band, satoshis)| BandSupply {
+                label: band.label.clone(),
+                supply: from_satoshis(satoshis),
+                share: if total > 0 { satoshis as f64 / total as f64 } else { 0.0 }
+            }).collect()
+        }
+    }
+
+    pub fn band(&self, label: &str) -> Option<&BandSupply> {
+        self.bands.iter().find(|band| band.label == label)
+    }
+
+    pub fn matches_active_coins(&self, stats: &ChainStats) -> bool {
+        to_satoshis(self.total) == to_satoshis(stats.total_active_coins())
+    }
+}
+
+// A snapshot every `step` blocks across `blocks`, always including the last block.
+// The unspent set is built once, block by block, instead of rescanning for each snapshot.
+pub fn hodl_waves(blocks: &[Block], bands: &AgeBands, step: i64) -> Vec<HodlSnapshot> {
+    let mut sorted: Vec<&Block> = blocks.iter().collect();
+    sorted.sort_by_key(|block| block.height());
+
+    // (funding hash, output index) -> (date, satoshis)
+    let mut unspent: HashMap<(String, i32), (i64, i64)> = HashMap::new();
+    let mut snapshots = vec![];
+    for (i, block) in sorted.iter().enumerate() {
+        for transaction in block.transactions() {
+            for amount in transaction.transaction_amounts() {
+                if amount.is_output() {
+                    unspent.insert((transaction.hash().clone(), amount.index()), (amount.date(), to_satoshis(amount.amount())));
+                } else if let Some(vin_hash) = amount.vin_hash() {
+                    unspent.remove(&(vin_hash.clone(), amount.vin_index()));
+                }
+            }
+        }
+
+        let is_last = i == sorted.len() - 1;
+        if is_last || (step > 0 && block.height() % step == 0) {
+            snapshots.push(HodlSnapshot::from_unspent(bands, block.height(), block.date(), unspent.values().copied()));
+        }
+    }
+    snapshots
+}
+
+fn from_satoshis(satoshis: i64) -> f64 {
+    satoshis as f64 / SATOSHIS_PER_COIN
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::hodl::*;
+    use crate::common::block::Block;
+    use crate::common::blockchain::{BlockChainNames, ChainStats};
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::spend_of;
+
+    // Block 1 (day 0) pays alice 2 and bob 3, block 2 (day 10) spends alice's output
+    fn blocks() -> Vec<Block> {
+        let mut first = Block::new("first".to_string(), 0, 1);
+        let mut funding = Transaction::new_from_block("funding".to_string(), false, &first);
+        funding.set_transaction_amounts(vec![
+            TransactionAmount::new(2.0, "alice".to_string(), "funding".to_string(), 0, 0),
+            TransactionAmount::new(3.0, "bob".to_string(), "funding".to_string(), 0, 1),
+        ]);
+        first.set_transactions(vec![funding]);
+
+        let mut second = Block::new("second".to_string(), 10 * DAY, 2);
+        let mut spend = Transaction::new_from_block("spend".to_string(), false, &second);
+        spend.set_transaction_amounts(vec![spend_of("funding", 0, "alice", "spend", 2.0, 10 * DAY), TransactionAmount::new(1.9, "carol".to_string(), "spend".to_string(), 10 * DAY, 0)]);
+        second.set_transactions(vec![spend]);
+
+        vec![first, second]
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let index = TransactionIndex::from_blocks(&blocks());
+        let snapshot = HodlSnapshot::at(&index, &AgeBands::default(), 2, 10 * DAY);
+
+        assert_eq!(snapshot.total(), 4.9);
+        assert_eq!(snapshot.band("<1d").unwrap().supply(), 1.9);
+        assert_eq!(snapshot.band("1w-1m").unwrap().supply(), 3.0);
+        assert_eq!(snapshot.band(">10y").unwrap().supply(), 0.0);
+
+        // Before the spend alice's output is still unspent
+        let earlier = HodlSnapshot::at(&index, &AgeBands::default(), 1, 10 * DAY);
+        assert_eq!(earlier.total(), 5.0);
+        assert_eq!(earlier.band("1w-1m").unwrap().share(), 1.0);
+
+        let mut stats = ChainStats::new(BlockChainNames::Bitcoin, "Bitcoin".to_string(), 0);
+        stats.update_total_active_coins(4.9);
+        assert!(snapshot.matches_active_coins(&stats));
+        assert!(!earlier.matches_active_coins(&stats));
+    }
+
+    #[test]
+    fn hodl_waves_test() {
+        let waves = hodl_waves(&blocks(), &AgeBands::default(), 1);
+        assert_eq!(waves.len(), 2);
+        assert_eq!(waves[0].block_height(), 1);
+        assert_eq!(waves[0].band("<1d").unwrap().supply(), 5.0);
+        assert_eq!(waves[1].date(), 10 * DAY);
+
+        let index = TransactionIndex::from_blocks(&blocks());
+        assert_eq!(waves[1], HodlSnapshot::at(&index, &AgeBands::default(), 2, 10 * DAY));
+    }
+
+    #[test]
+    fn custom_bands_test() {
+        let bands = AgeBands::from_json(r#"[
+            {"label": "young", "min_age": 0, "max_age": 86400},
+            {"label": "old", "min_age": 86400}
+        ]"#).unwrap();
+        assert_eq!(bands.band_index(100), 0);
+        assert_eq!(bands.band_index(86400), 1);
+        assert_eq!(serde_json::from_str::<AgeBands>(&serde_json::to_string(&bands).unwrap()).unwrap(), bands);
+
+        let gap = AgeBands::from_json(r#"[
+            {"label": "young", "min_age": 0, "max_age": 10},
+            {"label": "old", "min_age": 20}
+        ]"#);
+        assert!(gap.is_err());
+        assert!(serde_json::from_str::<AgeBands>("[]").is_err());
+        assert!(serde_json::from_str::<AgeBands>(r#"{"bands": []}"#).is_err());
+    }
+}
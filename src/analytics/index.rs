@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::common::block::Block;
+use crate::common::transaction::{Transaction, TransactionAmount};
+
+// In-memory lookup of transactions, outputs and the inputs spending them,
+// linked through TransactionAmount::vin_hash / vin_index
+#[derive(Debug, Clone, Default)]
+pub struct TransactionIndex {
+    transactions: HashMap<String, Transaction>,
+    spenders: HashMap<(String, i32), String> // (funding hash, output index) -> spending hash
+}
+
+impl TransactionIndex {
+    pub fn new() -> Self {
+        TransactionIndex::default()
+    }
+
+    pub fn from_blocks(blocks: &[Block]) -> Self {
+        let mut index = TransactionIndex::new();
+        for block in blocks {
+            index.insert_block(block);
+        }
+        index
+    }
+
+    pub fn insert_block(&mut self, block: &Block) -> &mut Self {
+        for transaction in block.transactions() {
+            self.insert_transaction(transaction.clone());
+        }
+        self
+    }
+
+    pub fn insert_transaction(&mut self, transaction: Transaction) -> &mut Self {
+        for amount in transaction.transaction_amounts().iter().filter(|a| a.is_input()) {
+            if let Some(vin_hash) = amount.vin_hash() {
+                self.spenders.insert((vin_hash.clone(), amount.vin_index()), transaction.hash().clone());
+            }
+        }
+        self.transactions.insert(transaction.hash().clone(), transaction);
+        self
+    }
+
+    pub fn get(&self, transaction_hash: &str) -> Option<&Transaction> {
+        self.transactions.get(transaction_hash)
+    }
+
+    pub fn output(&self, transaction_hash: &str, index: i32) -> Option<&TransactionAmount> {
+        self.get(transaction_hash)?
+            .transaction_amounts().iter()
+            .find(|a| a.is_output() && a.index() == index)
+    }
+
+    // The transaction spending an output, None while it's unspent (or not indexed)
+    pub fn spender(&self, transaction_hash: &str, index: i32) -> Option<&Transaction> {
+        let hash = self.spenders.get(&(transaction_hash.to_string(), index))?;
+        self.get(hash)
+    }
+
+    pub fn is_spent(&self, transaction_hash: &str, index: i32) -> bool {
+        self.spenders.contains_key(&(transaction_hash.to_string(), index))
+    }
+
+    // The output an input consumes
+    pub fn spent_output(&self, input: &TransactionAmount) -> Option<&TransactionAmount> {
+        self.output(input.vin_hash().as_ref()?, input.vin_index())
+    }
+
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::index::TransactionIndex;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::spend_of;
+
+    #[test]
+    fn spender_lookup_test() {
+        let mut funding = Transaction::new("funding".to_string(), false, 100, "blocky_hash".to_string(), 1);
+        funding.set_transaction_amounts(vec![TransactionAmount::new(5.0, "alice".to_string(), "funding".to_string(), 100, 0)]);
+
+        let mut spending = Transaction::new("spending".to_string(), false, 200, "blocky_hash".to_string(), 2);
+        let input = spend_of("funding", 0, "alice", "spending", 5.0, 200);
+        spending.set_transaction_amounts(vec![input.clone(), TransactionAmount::new(4.9, "bob".to_string(), "spending".to_string(), 200, 0)]);
+
+        let mut index = TransactionIndex::new();
+        index.insert_transaction(funding).insert_transaction(spending);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.spender("funding", 0).unwrap().hash(), &"spending".to_string());
+        assert!(index.spender("spending", 0).is_none());
+        assert_eq!(index.spent_output(&input).unwrap().date(), 100);
+        assert_eq!(index.output("spending", 0).unwrap().address_hash(), &"bob".to_string());
+    }
+}
@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use easy_error::bail;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::common::address::Address;
+use crate::common::blockchain::BlockChainNames;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelKind {
+    Exchange,
+    Mixer,
+    MiningPool,
+    Burn,
+    Sanctioned,
+    Other,
+}
+
+impl LabelKind {
+    pub fn from_name(name: &str) -> Result<Self, Box<dyn Error>> {
+        match name.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+            "exchange" => Ok(LabelKind::Exchange),
+            "mixer" => Ok(LabelKind::Mixer),
+            "mining_pool" | "miner_pool" | "pool" => Ok(LabelKind::MiningPool),
+            "burn" => Ok(LabelKind::Burn),
+            "sanctioned" => Ok(LabelKind::Sanctioned),
+            "other" => Ok(LabelKind::Other),
+            _ => bail!("Invalid label kind: {}", name),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct AddressLabel {
+    #[getset(get = "pub")]
+    address_hash: String,
+
+    #[getset(get = "pub")]
+    kind: LabelKind,
+
+    #[getset(get = "pub")]
+    entity: String, // e.g. "Binance"
+
+    // Where the label came from (file, analyst, vendor)
+    #[getset(get = "pub")]
+    #[serde(default)]
+    source: String,
+
+    #[getset(get_copy = "pub")]
+    #[serde(default = "default_confidence")]
+    confidence: f64
+}
+
+fn default_confidence() -> f64 {
+    1.0
+}
+
+impl AddressLabel {
+    pub fn new(address_hash: String, kind: LabelKind, entity: String, source: String, confidence: f64) -> Self {
+        AddressLabel { address_hash, kind, entity, source, confidence }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LabelRegistry {
+    labels: HashMap<BlockChainNames, HashMap<String, Vec<AddressLabel>>>
+}
+
+impl LabelRegistry {
+    pub fn new() -> Self {
+        LabelRegistry::default()
+    }
+
+    pub fn insert(&mut self, chain: BlockChainNames, label: AddressLabel) -> &mut Self {
+        let labels = self.labels.entry(chain).or_default()
+            .entry(label.address_hash.clone()).or_default();
+        labels.push(label);
+        // Highest confidence first so the primary label is always at the front
+        labels.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        self
+    }
+
+    // CSV columns: address_hash,kind,entity[,source[,confidence]] with a header row
+    pub fn load_csv(&mut self, chain: BlockChainNames, path: &Path) -> Result<usize, Box<dyn Error>> {
+        let raw = fs::read_to_string(path)?;
+        self.parse_csv(chain, &raw, &path.display().to_string())
+    }
+
+    pub fn parse_csv(&mut self, chain: BlockChainNames, raw: &str, default_source: &str) -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        for (line_number, line) in raw.lines().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue
+            }
+            let fields = split_csv_line(line);
+            if fields.len() < 3 {
+                bail!("Label csv line {} has {} columns, expected at least 3", line_number + 1, fields.len());
+            }
+            let source = fields.get(3).filter(|s| !s.is_empty()).cloned().unwrap_or_else(|| default_source.to_string());
+            let confidence = match fields.get(4).filter(|s| !s.is_empty()) {
+                Some(confidence) => confidence.parse::<f64>()?,
+                None => default_confidence(),
+            };
+            let label = AddressLabel::new(fields[0].clone(), LabelKind::from_name(&fields[1])?, fields[2].clone(), source, confidence);
+            self.insert(chain.clone(), label);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // JSON array of AddressLabel objects, source defaults to the file path
+    pub fn load_json(&mut self, chain: BlockChainNames, path: &Path) -> Result<usize, Box<dyn Error>> {
+        let raw = fs::read_to_string(path)?;
+        self.parse_json(chain, &raw, &path.display().to_string())
+    }
+
+    pub fn parse_json(&mut self, chain: BlockChainNames, raw: &str, default_source: &str) -> Result<usize, Box<dyn Error>> {
+        let labels: Vec<AddressLabel> = serde_json::from_str(raw)?;
+        let count = labels.len();
+        for mut label in labels {
+            if label.source.is_empty() {
+                label.source = default_source.to_string();
+            }
+            self.insert(chain.clone(), label);
+        }
+        Ok(count)
+    }
+
+    pub fn labels_for(&self, chain: &BlockChainNames, address_hash: &str) -> &[AddressLabel] {
+        self.labels.get(chain)
+            .and_then(|labels| labels.get(address_hash))
+            .map(|labels| labels.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn address_labels(&self, chain: &BlockChainNames, address: &Address) -> &[AddressLabel] {
+        self.labels_for(chain, address.hash())
+    }
+
+    pub fn primary_label(&self, chain: &BlockChainNames, address_hash: &str) -> Option<&AddressLabel> {
+        self.labels_for(chain, address_hash).first()
+    }
+
+    pub fn has_kind(&self, chain: &BlockChainNames, address_hash: &str, kind: &LabelKind) -> bool {
+        self.labels_for(chain, address_hash).iter().any(|l| &l.kind == kind)
+    }
+
+    pub fn len(&self, chain: &BlockChainNames) -> usize {
+        self.labels.get(chain).map(|labels| labels.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.values().all(|labels| labels.is_empty())
+    }
+
+    // Each address counts once, towards its primary label
+    pub fn supply_by_label(&self, chain: &BlockChainNames, addresses: &[Address], min_confidence: f64) -> LabelSupply {
+        let mut supply = LabelSupply::default();
+        for address in addresses {
+            let label = match self.primary_label(chain, address.hash()) {
+                Some(label) if label.confidence >= min_confidence => label,
+                _ => continue
+            };
+            let total = address.coin_total();
+            match label.kind {
+                LabelKind::Exchange => supply.exchange += total,
+                LabelKind::Mixer => supply.mixer += total,
+                LabelKind::MiningPool => supply.mining_pool += total,
+                LabelKind::Burn => supply.burn += total,
+                LabelKind::Sanctioned => supply.sanctioned += total,
+                LabelKind::Other => supply.other += total,
+            }
+        }
+        supply
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct LabelSupply {
+    exchange: f64,
+    mixer: f64,
+    mining_pool: f64,
+    burn: f64,
+    sanctioned: f64,
+    other: f64
+}
+
+// Splits a csv line, honouring double quoted fields with "" escapes
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::labels::*;
+    use crate::common::address::Address;
+    use crate::common::blockchain::BlockChainNames;
+
+    const CSV: &str = "address_hash,kind,entity,source,confidence\n\
+        1ExchangeHot,exchange,\"Exchange, Inc\",,0.9\n\
+        1ExchangeHot,mixer,Unknown mixer,analyst,0.4\n\
+        1Pool,mining pool,Pool,,\n\
+        1Burn,burn,Burn address,,1.0\n";
+
+    #[test]
+    fn parse_csv_test() {
+        let mut registry = LabelRegistry::new();
+        let count = registry.parse_csv(BlockChainNames::Bitcoin, CSV, "labels.csv").unwrap();
+
+        assert_eq!(count, 4);
+        assert_eq!(registry.len(&BlockChainNames::Bitcoin), 3);
+        let primary = registry.primary_label(&BlockChainNames::Bitcoin, "1ExchangeHot").unwrap();
+        assert_eq!(primary.kind(), &LabelKind::Exchange);
+        assert_eq!(primary.entity(), &"Exchange, Inc".to_string());
+        assert_eq!(primary.source(), &"labels.csv".to_string());
+        assert_eq!(registry.labels_for(&BlockChainNames::Bitcoin, "1ExchangeHot")[1].source(), &"analyst".to_string());
+        assert!(registry.labels_for(&BlockChainNames::Litecoin, "1ExchangeHot").is_empty());
+    }
+
+    #[test]
+    fn parse_csv_invalid_kind_test() {
+        let mut registry = LabelRegistry::new();
+        let result = registry.parse_csv(BlockChainNames::Bitcoin, "address_hash,kind,entity\n1Abc,casino,Casino\n", "labels.csv");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_json_test() {
+        let raw = r#"[
+            {"address_hash": "LExchange", "kind": "exchange", "entity": "Exchange", "confidence": 0.8},
+            {"address_hash": "LSanctioned", "kind": "sanctioned", "entity": "SDN", "source": "ofac"}
+        ]"#;
+        let mut registry = LabelRegistry::new();
+        registry.parse_json(BlockChainNames::Litecoin, raw, "labels.json").unwrap();
+
+        assert!(registry.has_kind(&BlockChainNames::Litecoin, "LSanctioned", &LabelKind::Sanctioned));
+        assert_eq!(registry.primary_label(&BlockChainNames::Litecoin, "LSanctioned").unwrap().confidence(), 1.0);
+        assert_eq!(registry.primary_label(&BlockChainNames::Litecoin, "LExchange").unwrap().source(), &"labels.json".to_string());
+    }
+
+    #[test]
+    fn supply_by_label_test() {
+        let mut registry = LabelRegistry::new();
+        registry.parse_csv(BlockChainNames::Bitcoin, CSV, "labels.csv").unwrap();
+
+        let mut addresses = vec![];
+        for (hash, total) in [("1ExchangeHot", 100.0), ("1Pool", 25.0), ("1Unlabeled", 7.0)] {
+            let mut address = Address::new(hash.to_string());
+            address.set_coin_total(total);
+            addresses.push(address);
+        }
+
+        let supply = registry.supply_by_label(&BlockChainNames::Bitcoin, &addresses, 0.5);
+        assert_eq!(supply.exchange(), 100.0);
+        assert_eq!(supply.mining_pool(), 25.0);
+        assert_eq!(supply.mixer(), 0.0);
+
+        let supply = registry.supply_by_label(&BlockChainNames::Bitcoin, &addresses, 0.95);
+        assert_eq!(supply.exchange(), 0.0);
+    }
+}
@@ -0,0 +1,17 @@
+pub mod clustering;
+pub mod change;
+pub mod patterns;
+pub mod index;
+pub mod taint;
+pub mod export;
+pub mod labels;
+pub mod screening;
+pub mod alerts;
+pub mod dormancy;
+pub mod hodl;
+pub mod distribution;
+pub mod cohorts;
+pub mod reuse;
+pub mod throughput;
+pub mod price;
+pub mod tax;
\ No newline at end of file
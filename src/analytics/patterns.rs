@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use getset::CopyGetters;
+use std::collections::HashSet;
+
+use crate::analytics::clustering::is_likely_coinjoin;
+use crate::common::block::Block;
+use crate::common::transaction::{Transaction, to_satoshis};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransactionPattern {
+    Coinbase,
+    CoinJoin,
+    SelfTransfer,
+    Consolidation,
+    BatchPayment,
+    PeelChain,
+    SimplePayment,
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PatternConfig {
+    pub consolidation_min_inputs: usize,
+    pub batch_min_outputs: usize,
+    pub batch_max_inputs: usize,
+    pub peel_max_ratio: f64, // peeled output as a share of the larger output
+}
+
+impl Default for PatternConfig {
+    fn default() -> Self {
+        PatternConfig {
+            consolidation_min_inputs: 3,
+            batch_min_outputs: 5,
+            batch_max_inputs: 3,
+            peel_max_ratio: 0.1
+        }
+    }
+}
+
+impl PatternConfig {
+    pub fn classify(&self, transaction: &Transaction) -> TransactionPattern {
+        if transaction.is_coinbase() {
+            return TransactionPattern::Coinbase
+        }
+        if is_likely_coinjoin(transaction) {
+            return TransactionPattern::CoinJoin
+        }
+
+        let amounts = transaction.transaction_amounts();
+        let inputs: Vec<_> = amounts.iter().filter(|a| a.is_input()).collect();
+        let outputs: Vec<_> = amounts.iter().filter(|a| a.is_output()).collect();
+        let input_addresses: HashSet<&String> = inputs.iter().map(|a| a.address_hash()).collect();
+
+        if inputs.is_empty() || outputs.is_empty() {
+            return TransactionPattern::Other
+        }
+        if outputs.iter().all(|o| input_addresses.contains(o.address_hash())) {
+            return TransactionPattern::SelfTransfer
+        }
+        if inputs.len() >= self.consolidation_min_inputs && outputs.len() == 1 {
+            return TransactionPattern::Consolidation
+        }
+        if outputs.len() >= self.batch_min_outputs && inputs.len() <= self.batch_max_inputs {
+            return TransactionPattern::BatchPayment
+        }
+        if inputs.len() == 1 && outputs.len() == 2 {
+            let (a, b) = (to_satoshis(outputs[0].amount()), to_satoshis(outputs[1].amount()));
+            let (small, large) = if a < b { (a, b) } else { (b, a) };
+            if large > 0 && (small as f64) <= large as f64 * self.peel_max_ratio {
+                return TransactionPattern::PeelChain
+            }
+        }
+        if outputs.len() <= 2 {
+            return TransactionPattern::SimplePayment
+        }
+        TransactionPattern::Other
+    }
+}
+
+pub fn classify(transaction: &Transaction) -> TransactionPattern {
+    PatternConfig::default().classify(transaction)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct PatternCounts {
+    coinbase: i64,
+    coinjoin: i64,
+    self_transfer: i64,
+    consolidation: i64,
+    batch_payment: i64,
+    peel_chain: i64,
+    simple_payment: i64,
+    other: i64
+}
+
+impl PatternCounts {
+    pub fn new() -> Self {
+        PatternCounts::default()
+    }
+
+    pub fn add(&mut self, pattern: TransactionPattern) -> &mut Self {
+        match pattern {
+            TransactionPattern::Coinbase => self.coinbase += 1,
+            TransactionPattern::CoinJoin => self.coinjoin += 1,
+            TransactionPattern::SelfTransfer => self.self_transfer += 1,
+            TransactionPattern::Consolidation => self.consolidation += 1,
+            TransactionPattern::BatchPayment => self.batch_payment += 1,
+            TransactionPattern::PeelChain => self.peel_chain += 1,
+            TransactionPattern::SimplePayment => self.simple_payment += 1,
+            TransactionPattern::Other => self.other += 1,
+        }
+        self
+    }
+
+    pub fn add_block(&mut self, block: &Block, config: &PatternConfig) -> &mut Self {
+        for transaction in block.transactions() {
+            self.add(config.classify(transaction));
+        }
+        self
+    }
+
+    // Counts blocks falling inside [date_start, date_end]
+    pub fn from_blocks(blocks: &[Block], date_start: i64, date_end: i64, config: &PatternConfig) -> Self {
+        let mut counts = PatternCounts::new();
+        for block in blocks.iter().filter(|b| b.date() >= date_start && b.date() <= date_end) {
+            counts.add_block(block, config);
+        }
+        counts
+    }
+
+    pub fn total(&self) -> i64 {
+        self.coinbase + self.coinjoin + self.self_transfer + self.consolidation
+            + self.batch_payment + self.peel_chain + self.simple_payment + self.other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::patterns::*;
+    use crate::common::block::Block;
+    use crate::common::transaction::Transaction;
+    use crate::test_fixtures::transaction;
+
+    #[test]
+    fn classify_test() {
+        assert_eq!(classify(&transaction(&[("a", 1.1), ("b", 1.1), ("c", 1.1)], &[("x", 1.0), ("y", 1.0), ("z", 1.0)])), TransactionPattern::CoinJoin);
+        assert_eq!(classify(&transaction(&[("a", 1.0), ("b", 1.0), ("c", 1.0)], &[("x", 2.9)])), TransactionPattern::Consolidation);
+        assert_eq!(classify(&transaction(&[("a", 10.0)], &[("v", 1.0), ("w", 2.0), ("x", 3.0), ("y", 1.5), ("z", 2.4)])), TransactionPattern::BatchPayment);
+        assert_eq!(classify(&transaction(&[("a", 1.0)], &[("a", 0.99)])), TransactionPattern::SelfTransfer);
+        assert_eq!(classify(&transaction(&[("a", 10.0)], &[("x", 0.5), ("y", 9.4)])), TransactionPattern::PeelChain);
+        assert_eq!(classify(&transaction(&[("a", 10.0)], &[("x", 4.0), ("y", 5.9)])), TransactionPattern::SimplePayment);
+    }
+
+    #[test]
+    fn classify_coinbase_test() {
+        let block = Block::new("blocky_hash".to_string(), 123456789, 420);
+        let coinbase = Transaction::new_from_block("coinbase".to_string(), true, &block);
+        assert_eq!(classify(&coinbase), TransactionPattern::Coinbase);
+    }
+
+    #[test]
+    fn pattern_counts_from_blocks_test() {
+        let mut block = Block::new("blocky_hash".to_string(), 123456789, 420);
+        block.set_transactions(vec![
+            Transaction::new_from_block("coinbase".to_string(), true, &block),
+            transaction(&[("a", 10.0)], &[("x", 0.5), ("y", 9.4)]),
+            transaction(&[("a", 10.0)], &[("x", 4.0), ("y", 5.9)]),
+        ]);
+        let outside = Block::new("old_block".to_string(), 100, 1);
+
+        let counts = PatternCounts::from_blocks(&[block, outside], 123456000, 123457000, &PatternConfig::default());
+
+        assert_eq!(counts.coinbase(), 1);
+        assert_eq!(counts.peel_chain(), 1);
+        assert_eq!(counts.simple_payment(), 1);
+        assert_eq!(counts.total(), 3);
+    }
+}
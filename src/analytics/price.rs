@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use chrono::{NaiveDate, TimeZone, Utc};
+use easy_error::bail;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::analytics::index::TransactionIndex;
+use crate::analytics::labels::split_csv_line;
+use crate::common::blockchain::BlockChain;
+use crate::common::period::Period;
+use crate::common::transaction::TransactionAmount;
+
+// How to price a day missing from the series
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Exact,    // no price unless the day is in the series
+    #[default]
+    Previous, // carry the last known price forward
+    Nearest,  // closest known day, earlier wins ties
+    Linear,   // straight line between the surrounding known days
+}
+
+// Daily prices keyed by the start of each UTC day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, Getters, CopyGetters)]
+pub struct PriceSeries {
+    #[getset(get = "pub")]
+    ticker: String,
+
+    #[getset(get_copy = "pub")]
+    interpolation: Interpolation,
+
+    prices: BTreeMap<i64, f64>
+}
+
+impl PriceSeries {
+    pub fn new(ticker: String, interpolation: Interpolation) -> Self {
+        PriceSeries { ticker, interpolation, prices: BTreeMap::new() }
+    }
+
+    pub fn load_csv(path: &Path, ticker: String, interpolation: Interpolation) -> Result<Self, Box<dyn Error>> {
+        let raw = fs::read_to_string(path)?;
+        PriceSeries::parse_csv(&raw, ticker, interpolation)
+    }
+
+    // A header row naming a date column and a price or close column, dates as
+    // YYYY-MM-DD or unix seconds
+    pub fn parse_csv(raw: &str, ticker: String, interpolation: Interpolation) -> Result<Self, Box<dyn Error>> {
+        let mut lines = raw.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let header = match lines.next() {
+            Some((_, header)) => split_csv_line(&header.to_lowercase()),
+            None => bail!("Price csv for {} is empty", ticker),
+        };
+        let date_column = header.iter().position(|c| c == "date" || c == "time" || c == "timestamp").unwrap_or(0);
+        let price_column = match header.iter().position(|c| c == "price" || c == "close") {
+            Some(column) => column,
+            None => bail!("Price csv for {} has no price or close column", ticker),
+        };
+
+        let mut series = PriceSeries::new(ticker, interpolation);
+        for (line_number, line) in lines {
+            let fields = split_csv_line(line);
+            let (date, price) = match (fields.get(date_column), fields.get(price_column)) {
+                (Some(date), Some(price)) => (date, price),
+                _ => bail!("Price csv line {} is missing columns", line_number + 1),
+            };
+            series.insert(parse_date(date)?, price.parse::<f64>()?);
+        }
+        Ok(series)
+    }
+
+    pub fn insert(&mut self, time: i64, price: f64) -> &mut Self {
+        self.prices.insert(Period::Day.start_of(time), price);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.prices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    pub fn price_at(&self, time: i64) -> Option<f64> {
+        let day = Period::Day.start_of(time);
+        if let Some(price) = self.prices.get(&day) {
+            return Some(*price)
+        }
+        let before = self.prices.range(..day).next_back();
+        let after = self.prices.range(day..).next();
+        match self.interpolation {
+            Interpolation::Exact => None,
+            Interpolation::Previous => before.map(|(_, price)| *price),
+            Interpolation::Nearest => match (before, after) {
+                (Some(b), Some(a)) => Some(if day - b.0 <= a.0 - day { *b.1 } else { *a.1 }),
+                (Some((_, price)), None) | (None, Some((_, price))) => Some(*price),
+                (None, None) => None,
+            },
+            Interpolation::Linear => {
+                let ((before_day, before_price), (after_day, after_price)) = (before?, after?);
+                let weight = (day - before_day) as f64 / (after_day - before_day) as f64;
+                Some(before_price + (after_price - before_price) * weight)
+            },
+        }
+    }
+
+    // Fiat value of an amount on its own date, negative for inputs
+    pub fn value(&self, amount: &TransactionAmount) -> Option<f64> {
+        Some(amount.amount() * self.price_at(amount.date())?)
+    }
+}
+
+// Price series for several chains keyed by BlockChain::short_description
+#[derive(Debug, Clone, Default)]
+pub struct PriceBook {
+    series: HashMap<String, PriceSeries>
+}
+
+impl PriceBook {
+    pub fn new() -> Self {
+        PriceBook::default()
+    }
+
+    pub fn insert(&mut self, series: PriceSeries) -> &mut Self {
+        self.series.insert(series.ticker.to_uppercase(), series);
+        self
+    }
+
+    pub fn load_csv(&mut self, chain: &BlockChain, path: &Path, interpolation: Interpolation) -> Result<&PriceSeries, Box<dyn Error>> {
+        let series = PriceSeries::load_csv(path, chain.short_description().clone(), interpolation)?;
+        self.insert(series);
+        Ok(self.for_chain(chain).unwrap())
+    }
+
+    pub fn for_chain(&self, chain: &BlockChain) -> Option<&PriceSeries> {
+        self.for_ticker(chain.short_description())
+    }
+
+    pub fn for_ticker(&self, ticker: &str) -> Option<&PriceSeries> {
+        self.series.get(&ticker.to_uppercase())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct MarketStats {
+    price: f64,
+    market_cap: f64,
+    realized_cap: f64,   // unspent outputs valued at their creation price
+    unpriced_supply: f64, // unspent outputs created on days without a price
+    mvrv: f64,
+    nvt: f64             // market cap over the fiat value of `volume`
+}
+
+impl MarketStats {
+    // Unspent outputs as of `time`. `supply` is normally ChainStats::total_active_coins
+    // and `volume` the coins transferred in the window (e.g. ChainStats::adjusted_volume).
+    pub fn compute(index: &TransactionIndex, series: &PriceSeries, time: i64, supply: f64, volume: f64) -> Option<Self> {
+        let price = series.price_at(time)?;
+        let mut stats = MarketStats { price, market_cap: supply * price, ..MarketStats::default() };
+
+        for transaction in index.transactions().filter(|t| t.date() <= time) {
+            for output in transaction.transaction_amounts().iter().filter(|a| a.is_output()) {
+                let spent = index.spender(transaction.hash(), output.index())
+                    .map(|spender| spender.date() <= time)
+                    .unwrap_or(false);
+                if spent {
+                    continue
+                }
+                match series.value(output) {
+                    Some(value) => stats.realized_cap += value,
+                    None => stats.unpriced_supply += output.amount(),
+                }
+            }
+        }
+
+        if stats.realized_cap > 0.0 {
+            stats.mvrv = stats.market_cap / stats.realized_cap;
+        }
+        if volume > 0.0 {
+            stats.nvt = stats.market_cap / (volume * price);
+        }
+        Some(stats)
+    }
+}
+
+fn parse_date(raw: &str) -> Result<i64, Box<dyn Error>> {
+    if let Ok(time) = raw.parse::<i64>() {
+        return Ok(time)
+    }
+    let date = NaiveDate::parse_from_str(raw.get(..10).unwrap_or(raw), "%Y-%m-%d")?;
+    Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()).timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::index::TransactionIndex;
+    use crate::analytics::price::*;
+    use crate::common::blockchain::{BlockChain, BlockChainNames};
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::spend_of;
+
+    const DAY: i64 = 86400;
+    const JAN_1: i64 = 1672531200; // 2023-01-01
+
+    const CSV: &str = "Date,Open,Close\n2023-01-01,9,10\n2023-01-03,25,30\n2023-01-04,39,40\n";
+
+    #[test]
+    fn parse_csv_test() {
+        let series = PriceSeries::parse_csv(CSV, "BTC".to_string(), Interpolation::Exact).unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series.price_at(JAN_1 + 3600), Some(10.0));
+        assert_eq!(series.price_at(JAN_1 + DAY), None);
+
+        assert!(PriceSeries::parse_csv("date,volume\n2023-01-01,5\n", "BTC".to_string(), Interpolation::Exact).is_err());
+        assert!(PriceSeries::parse_csv("date,price\nyesterday,5\n", "BTC".to_string(), Interpolation::Exact).is_err());
+    }
+
+    #[test]
+    fn interpolation_test() {
+        let mut series = PriceSeries::parse_csv(CSV, "BTC".to_string(), Interpolation::Previous).unwrap();
+        assert_eq!(series.price_at(JAN_1 + DAY), Some(10.0));
+        assert_eq!(series.price_at(JAN_1 - DAY), None);
+        assert_eq!(series.price_at(JAN_1 + 10 * DAY), Some(40.0));
+
+        series.interpolation = Interpolation::Linear;
+        assert_eq!(series.price_at(JAN_1 + DAY), Some(20.0));
+        assert_eq!(series.price_at(JAN_1 + 10 * DAY), None);
+
+        series.interpolation = Interpolation::Nearest;
+        assert_eq!(series.price_at(JAN_1 + DAY), Some(10.0));
+        assert_eq!(series.price_at(JAN_1 - DAY), Some(10.0));
+
+        let amount = TransactionAmount::new(-0.5, "alice".to_string(), "hashy".to_string(), JAN_1 + 3 * DAY, -1);
+        assert_eq!(series.value(&amount), Some(-20.0));
+    }
+
+    #[test]
+    fn price_book_test() {
+        let mut book = PriceBook::new();
+        book.insert(PriceSeries::parse_csv(CSV, "ltc".to_string(), Interpolation::Previous).unwrap());
+        assert!(book.for_chain(&BlockChain::new(BlockChainNames::Litecoin)).is_some());
+        assert!(book.for_chain(&BlockChain::new(BlockChainNames::Bitcoin)).is_none());
+    }
+
+    #[test]
+    fn market_stats_test() {
+        let series = PriceSeries::parse_csv(CSV, "BTC".to_string(), Interpolation::Previous).unwrap();
+        let mut funding = Transaction::new("funding".to_string(), false, JAN_1, "blocky_hash".to_string(), 1);
+        funding.set_transaction_amounts(vec![
+            TransactionAmount::new(2.0, "alice".to_string(), "funding".to_string(), JAN_1, 0),
+            TransactionAmount::new(1.0, "bob".to_string(), "funding".to_string(), JAN_1, 1),
+        ]);
+        let mut spend = Transaction::new("spend".to_string(), false, JAN_1 + 2 * DAY, "blocky_hash".to_string(), 2);
+        spend.set_transaction_amounts(vec![spend_of("funding", 0, "alice", "spend", 2.0, JAN_1 + 2 * DAY), TransactionAmount::new(2.0, "carol".to_string(), "spend".to_string(), JAN_1 + 2 * DAY, 0)]);
+        let mut index = TransactionIndex::new();
+        index.insert_transaction(funding).insert_transaction(spend);
+
+        // bob's coin at 10 and carol's two at 30
+        let stats = MarketStats::compute(&index, &series, JAN_1 + 3 * DAY, 3.0, 2.0).unwrap();
+        assert_eq!(stats.price(), 40.0);
+        assert_eq!(stats.market_cap(), 120.0);
+        assert_eq!(stats.realized_cap(), 70.0);
+        assert_eq!(stats.mvrv(), 120.0 / 70.0);
+        assert_eq!(stats.nvt(), 1.5);
+
+        assert!(MarketStats::compute(&index, &series, JAN_1 - DAY, 3.0, 2.0).is_none());
+    }
+}
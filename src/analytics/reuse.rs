@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use getset::CopyGetters;
+use std::collections::HashSet;
+
+use crate::common::address::{Address, AddressType};
+use crate::common::block::Block;
+use crate::common::transaction::{decode_hex, TransactionAmount};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct AddressReuse {
+    receive_count: i64,
+    spend_count: i64,
+    received_after_spend: bool,
+    pubkey_exposed: bool
+}
+
+impl AddressReuse {
+    // From the address history up to and including `time`
+    pub fn for_address(address: &Address, time: i64) -> Self {
+        let mut reuse = AddressReuse::default();
+        let mut spends = vec![];
+        for amount in address.history().until(time) {
+            if amount.is_input() {
+                reuse.spend_count += 1;
+                spends.push(amount);
+            } else {
+                reuse.receive_count += 1;
+                if reuse.spend_count > 0 {
+                    reuse.received_after_spend = true;
+                }
+            }
+        }
+        reuse.pubkey_exposed = pubkey_exposed(AddressType::from_hash(address.hash()), &spends);
+        reuse
+    }
+
+    pub fn is_reused(&self) -> bool {
+        self.receive_count > 1
+    }
+}
+
+// Taproot outputs commit to the key itself. Everything else only reveals the public key
+// in the input that spends it, so check each spend's scriptSig / witness. Spends without
+// script data (and P2PK outputs, which land in Unknown) are assumed to reveal it.
+pub fn pubkey_exposed(address_type: AddressType, spends: &[&TransactionAmount]) -> bool {
+    match address_type {
+        AddressType::P2TR => true,
+        _ => spends.iter().any(|spend| {
+            let has_script_data = spend.script_sig().is_some() || !spend.witness().is_empty();
+            !has_script_data || reveals_pubkey(spend)
+        }),
+    }
+}
+
+// A key pushed by the scriptSig or witness, or one inside the redeem / witness script pushed last
+fn reveals_pubkey(spend: &TransactionAmount) -> bool {
+    let mut items: Vec<Vec<u8>> = spend.witness().iter().filter_map(|item| decode_hex(item)).collect();
+    if let Some(script_sig) = spend.script_sig().as_ref().and_then(|s| decode_hex(s)) {
+        items.extend(script_pushes(&script_sig).into_iter().map(|push| push.to_vec()));
+    }
+    let script = items.last().map(|item| script_pushes(item)).unwrap_or_default();
+    items.iter().map(|item| item.as_slice()).chain(script).any(is_pubkey)
+}
+
+// Compressed or uncompressed SEC encoding
+fn is_pubkey(bytes: &[u8]) -> bool {
+    (bytes.len() == 33 && matches!(bytes[0], 0x02 | 0x03)) || (bytes.len() == 65 && bytes[0] == 0x04)
+}
+
+// Data pushed by a script, stopping at the first truncated push
+fn script_pushes(script: &[u8]) -> Vec<&[u8]> {
+    let mut pushes = vec![];
+    let mut i = 0;
+    while i < script.len() {
+        let opcode = script[i];
+        let (len, header) = match opcode {
+            0x01..=0x4b => (opcode as usize, 1),
+            0x4c => match script.get(i + 1) {
+                Some(len) => (*len as usize, 2),
+                None => break
+            },
+            0x4d => match script.get(i + 1..i + 3) {
+                Some(len) => (u16::from_le_bytes([len[0], len[1]]) as usize, 3),
+                None => break
+            },
+            _ => {
+                i += 1;
+                continue
+            }
+        };
+        match script.get(i + header..i + header + len) {
+            Some(data) => pushes.push(data),
+            None => break
+        }
+        i += header + len;
+    }
+    pushes
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct ReuseStats {
+    output_count: i64,
+    reused_output_count: i64, // outputs paying an address that had already received
+    reused_address_count: i64,
+    received_after_spend_count: i64,
+    exposed_pubkey_count: i64,
+    exposed_pubkey_supply: f64
+}
+
+impl ReuseStats {
+    // Output reuse inside [date_start, date_end], with blocks before the window used
+    // to learn which addresses had already received. Address metrics are as of date_end.
+    pub fn for_window(blocks: &[Block], addresses: &[Address], date_start: i64, date_end: i64) -> Self {
+        let mut stats = ReuseStats::default();
+
+        let mut sorted: Vec<&Block> = blocks.iter().filter(|b| b.date() <= date_end).collect();
+        sorted.sort_by_key(|block| block.height());
+        let mut received: HashSet<&String> = HashSet::new();
+        for block in sorted {
+            for transaction in block.transactions() {
+                let in_window = transaction.date() >= date_start;
+                for output in transaction.transaction_amounts().iter().filter(|a| a.is_output()) {
+                    let seen = !received.insert(output.address_hash());
+                    if in_window {
+                        stats.output_count += 1;
+                        if seen {
+                            stats.reused_output_count += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        for address in addresses {
+            let reuse = AddressReuse::for_address(address, date_end);
+            if reuse.is_reused() {
+                stats.reused_address_count += 1;
+            }
+            if reuse.received_after_spend {
+                stats.received_after_spend_count += 1;
+            }
+            if reuse.pubkey_exposed {
+                stats.exposed_pubkey_count += 1;
+                stats.exposed_pubkey_supply += address.balance_at_time(date_end);
+            }
+        }
+        stats
+    }
+
+    pub fn reused_output_share(&self) -> f64 {
+        if self.output_count > 0 {
+            self.reused_output_count as f64 / self.output_count as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::reuse::*;
+    use crate::common::address::AddressType;
+    use crate::common::block::Block;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::{address, input};
+
+    const P2PKH: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const P2TR: &str = "bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297";
+    const P2WPKH: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const P2WSH: &str = "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3";
+
+    #[test]
+    fn address_reuse_test() {
+        let reused = address(P2PKH, vec![
+            TransactionAmount::new(1.0, P2PKH.to_string(), "a".to_string(), 10, 0),
+            input(P2PKH, "hashy", 1.0, 20),
+            TransactionAmount::new(0.5, P2PKH.to_string(), "b".to_string(), 30, 0),
+        ]);
+        let reuse = AddressReuse::for_address(&reused, 100);
+        assert_eq!(reuse.receive_count(), 2);
+        assert!(reuse.is_reused());
+        assert!(reuse.received_after_spend());
+        assert!(reuse.pubkey_exposed());
+
+        let earlier = AddressReuse::for_address(&reused, 15);
+        assert!(!earlier.is_reused());
+        assert!(!earlier.pubkey_exposed());
+
+        assert!(pubkey_exposed(AddressType::P2TR, &[]));
+        assert!(pubkey_exposed(AddressType::Unknown, &[&input(P2PKH, "hashy", 1.0, 20)]));
+    }
+
+    #[test]
+    fn reveals_pubkey_test() {
+        let signature = format!("30{}01", "ab".repeat(70));
+        let pubkey = format!("02{}", "cd".repeat(32));
+
+        let mut p2wpkh = input(P2WPKH, "hashy", 1.0, 20);
+        p2wpkh.set_witness(vec![signature.clone(), pubkey.clone()]);
+        assert!(reveals_pubkey(&p2wpkh));
+
+        let mut p2pkh = input(P2PKH, "hashy", 1.0, 20);
+        p2pkh.set_script_sig(Some(format!("48{}21{}", signature, pubkey)));
+        assert!(reveals_pubkey(&p2pkh));
+
+        // 1 of 1 multisig witness script: OP_1 <pubkey> OP_1 OP_CHECKMULTISIG
+        let mut multisig = input(P2WSH, "hashy", 1.0, 20);
+        multisig.set_witness(vec![String::new(), signature, format!("5121{}51ae", pubkey)]);
+        assert!(reveals_pubkey(&multisig));
+
+        // Hash lock: the preimage and OP_SHA256 <hash> OP_EQUAL
+        let mut hashlock = input(P2WSH, "hashy", 1.0, 20);
+        hashlock.set_witness(vec!["11".repeat(32), format!("a820{}87", "22".repeat(32))]);
+        assert!(!reveals_pubkey(&hashlock));
+    }
+
+    #[test]
+    fn pubkey_from_spend_test() {
+        let mut hashlock = input(P2WSH, "hashy", 1.0, 20);
+        hashlock.set_witness(vec!["11".repeat(32), format!("a820{}87", "22".repeat(32))]);
+        let mut keyed = input(P2WSH, "hashy", 1.0, 30);
+        keyed.set_witness(vec![String::new(), format!("30{}01", "ab".repeat(70)), format!("5121{}51ae", "02".repeat(33))]);
+
+        let spent = address(P2WSH, vec![TransactionAmount::new(2.0, P2WSH.to_string(), "a".to_string(), 10, 0), hashlock, keyed]);
+        assert!(!AddressReuse::for_address(&spent, 25).pubkey_exposed());
+        assert!(AddressReuse::for_address(&spent, 35).pubkey_exposed());
+    }
+
+    #[test]
+    fn window_stats_test() {
+        let mut blocks = vec![];
+        for (height, date, receiver) in [(1, 10, P2PKH), (2, 110, P2PKH), (3, 120, P2TR)] {
+            let mut block = Block::new(format!("block{}", height), date, height);
+            let mut transaction = Transaction::new_from_block(format!("tx{}", height), false, &block);
+            transaction.set_transaction_amounts(vec![TransactionAmount::new(1.0, receiver.to_string(), format!("tx{}", height), date, 0)]);
+            block.set_transactions(vec![transaction]);
+            blocks.push(block);
+        }
+        let addresses = vec![
+            address(P2PKH, vec![
+                TransactionAmount::new(1.0, P2PKH.to_string(), "tx1".to_string(), 10, 0),
+                TransactionAmount::new(1.0, P2PKH.to_string(), "tx2".to_string(), 110, 0),
+            ]),
+            address(P2TR, vec![TransactionAmount::new(1.0, P2TR.to_string(), "tx3".to_string(), 120, 0)]),
+        ];
+
+        let stats = ReuseStats::for_window(&blocks, &addresses, 100, 200);
+        assert_eq!(stats.output_count(), 2);
+        assert_eq!(stats.reused_output_count(), 1);
+        assert_eq!(stats.reused_output_share(), 0.5);
+        assert_eq!(stats.reused_address_count(), 1);
+        assert_eq!(stats.exposed_pubkey_count(), 1);
+        assert_eq!(stats.exposed_pubkey_supply(), 1.0);
+    }
+}
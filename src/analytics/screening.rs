@@ -0,0 +1,313 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::analytics::labels::split_csv_line;
+use crate::common::block::Block;
+use crate::common::transaction::Transaction;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming, // the screened address received funds
+    Outgoing, // the screened address spent funds
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exposure {
+    Direct,
+    Counterparty, // trades with a listed address in the same transaction
+    OneHop, // received funds a listed address paid into one of this transaction's inputs
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct ScreeningAlert {
+    #[getset(get = "pub")]
+    block_hash: String,
+
+    #[getset(get_copy = "pub")]
+    block_height: i64,
+
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get = "pub")]
+    address_hash: String,
+
+    #[getset(get_copy = "pub")]
+    direction: Direction,
+
+    #[getset(get_copy = "pub")]
+    amount: f64,
+
+    #[getset(get_copy = "pub")]
+    exposure: Exposure,
+
+    // The listed address behind a counterparty or one hop alert
+    #[getset(get = "pub")]
+    listed_address: String
+}
+
+// Transactions listed addresses paid into, keyed by hash with the listed senders. Only
+// these are kept, so one hop exposure resolves while streaming blocks in chain order.
+#[derive(Debug, Clone, Default)]
+pub struct ListedFunding {
+    transactions: HashMap<String, Vec<String>>
+}
+
+impl ListedFunding {
+    pub fn new() -> Self {
+        ListedFunding::default()
+    }
+
+    pub fn senders(&self, transaction_hash: &str) -> Option<&Vec<String>> {
+        self.transactions.get(transaction_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+// Addresses loaded from a local list (e.g. an OFAC SDN digital currency address extract).
+// Lookups are a single hash set probe so full chain rescans stay cheap.
+#[derive(Debug, Clone, Default, Getters)]
+pub struct Watchlist {
+    addresses: HashSet<String>,
+
+    #[getset(get = "pub")]
+    source: String
+}
+
+impl Watchlist {
+    pub fn new(source: String) -> Self {
+        Watchlist { addresses: HashSet::new(), source }
+    }
+
+    // One address per line, or a csv whose first column is the address.
+    // Blank lines, # comments and an "address" header are skipped.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let raw = fs::read_to_string(path)?;
+        Ok(Watchlist::parse(&raw, path.display().to_string()))
+    }
+
+    pub fn parse(raw: &str, source: String) -> Self {
+        let mut watchlist = Watchlist::new(source);
+        for line in raw.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let address = split_csv_line(line).swap_remove(0);
+            if address.is_empty() || address.eq_ignore_ascii_case("address") || address.eq_ignore_ascii_case("address_hash") {
+                continue
+            }
+            watchlist.insert(address);
+        }
+        watchlist
+    }
+
+    pub fn insert(&mut self, address_hash: String) -> &mut Self {
+        self.addresses.insert(address_hash);
+        self
+    }
+
+    pub fn contains(&self, address_hash: &str) -> bool {
+        self.addresses.contains(address_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    // Walks the blocks in height order, remembering what listed addresses paid into
+    pub fn screen_blocks(&self, blocks: &[Block]) -> Vec<ScreeningAlert> {
+        let mut sorted: Vec<&Block> = blocks.iter().collect();
+        sorted.sort_by_key(|block| block.height());
+        let mut funding = ListedFunding::new();
+        sorted.iter().flat_map(|block| self.screen_block(&mut funding, block)).collect()
+    }
+
+    pub fn screen_block(&self, funding: &mut ListedFunding, block: &Block) -> Vec<ScreeningAlert> {
+        block.transactions().iter()
+            .flat_map(|transaction| self.screen_transaction(funding, transaction, block.hash(), block.height()))
+            .collect()
+    }
+
+    pub fn screen_transaction(&self, funding: &mut ListedFunding, transaction: &Transaction, block_hash: &str, block_height: i64) -> Vec<ScreeningAlert> {
+        let amounts = transaction.transaction_amounts();
+        let listed_inputs: Vec<&String> = amounts.iter()
+            .filter(|a| a.is_input() && self.contains(a.address_hash()))
+            .map(|a| a.address_hash())
+            .collect();
+        let listed_outputs: Vec<&String> = amounts.iter()
+            .filter(|a| a.is_output() && self.contains(a.address_hash()))
+            .map(|a| a.address_hash())
+            .collect();
+
+        // Listed addresses that paid into the outputs this transaction spends
+        let mut funded_by: Vec<&String> = vec![];
+        for input in amounts.iter().filter(|a| a.is_input()) {
+            let Some(senders) = input.vin_hash().as_ref().and_then(|hash| funding.senders(hash)) else { continue };
+            for sender in senders {
+                if !funded_by.contains(&sender) {
+                    funded_by.push(sender);
+                }
+            }
+        }
+
+        let mut alerts = vec![];
+        for amount in amounts {
+            let direction = if amount.is_input() { Direction::Outgoing } else { Direction::Incoming };
+            let mut push = |exposure: Exposure, listed_address: &String| alerts.push(ScreeningAlert {
+                block_hash: block_hash.to_string(),
+                block_height,
+                transaction_hash: transaction.hash().clone(),
+                address_hash: amount.address_hash().clone(),
+                direction,
+                amount: amount.amount().abs(),
+                exposure,
+                listed_address: listed_address.clone()
+            });
+
+            if self.contains(amount.address_hash()) {
+                push(Exposure::Direct, amount.address_hash());
+                continue
+            }
+            // Receiving from a listed sender, or paying into a listed receiver
+            let counterparties = if amount.is_output() { &listed_inputs } else { &listed_outputs };
+            for listed in counterparties {
+                push(Exposure::Counterparty, listed);
+            }
+            if amount.is_output() && listed_inputs.is_empty() {
+                for listed in &funded_by {
+                    push(Exposure::OneHop, listed);
+                }
+            }
+        }
+
+        if !listed_inputs.is_empty() {
+            let mut senders: Vec<String> = listed_inputs.into_iter().cloned().collect();
+            senders.sort();
+            senders.dedup();
+            funding.transactions.insert(transaction.hash().clone(), senders);
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::screening::*;
+    use crate::test_fixtures::{block, spend_of, transaction, transaction_at, DATE};
+
+    #[test]
+    fn parse_watchlist_test() {
+        let watchlist = Watchlist::parse("# SDN extract\naddress,entity\n1Sanctioned,\"Some, Entity\"\n\n1Other\n", "sdn.csv".to_string());
+
+        assert_eq!(watchlist.len(), 2);
+        assert!(watchlist.contains("1Sanctioned"));
+        assert!(!watchlist.contains("address"));
+        assert_eq!(watchlist.source(), &"sdn.csv".to_string());
+    }
+
+    #[test]
+    fn direct_and_counterparty_outgoing_test() {
+        let watchlist = Watchlist::parse("1Sanctioned", "sdn".to_string());
+        let blocks = [block(vec![transaction(&[("1Sanctioned", 5.0)], &[("1Receiver", 4.9)])])];
+
+        let alerts = watchlist.screen_blocks(&blocks);
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].exposure(), Exposure::Direct);
+        assert_eq!(alerts[0].direction(), Direction::Outgoing);
+        assert_eq!(alerts[0].amount(), 5.0);
+        assert_eq!(alerts[1].address_hash(), &"1Receiver".to_string());
+        assert_eq!(alerts[1].exposure(), Exposure::Counterparty);
+        assert_eq!(alerts[1].listed_address(), &"1Sanctioned".to_string());
+        assert_eq!(alerts[1].block_height(), 420);
+    }
+
+    #[test]
+    fn counterparty_incoming_test() {
+        let watchlist = Watchlist::parse("1Sanctioned", "sdn".to_string());
+        let blocks = [block(vec![transaction(&[("1Payer", 2.0)], &[("1Sanctioned", 1.5), ("1Change", 0.49)])])];
+
+        let alerts = watchlist.screen_blocks(&blocks);
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].address_hash(), &"1Payer".to_string());
+        assert_eq!(alerts[0].exposure(), Exposure::Counterparty);
+        assert_eq!(alerts[1].direction(), Direction::Incoming);
+        assert_eq!(alerts[1].exposure(), Exposure::Direct);
+    }
+
+    #[test]
+    fn every_listed_amount_test() {
+        let watchlist = Watchlist::parse("1Sanctioned\n1Other", "sdn".to_string());
+        let blocks = [block(vec![transaction(&[("1Sanctioned", 1.0), ("1Other", 2.0)], &[("1Receiver", 2.9)])])];
+
+        let alerts = watchlist.screen_blocks(&blocks);
+
+        assert_eq!(alerts.iter().filter(|a| a.exposure() == Exposure::Direct).count(), 2);
+        let counterparty: Vec<&String> = alerts.iter()
+            .filter(|a| a.exposure() == Exposure::Counterparty)
+            .map(|a| a.listed_address())
+            .collect();
+        assert_eq!(counterparty, vec!["1Sanctioned", "1Other"]);
+    }
+
+    #[test]
+    fn one_hop_test() {
+        let watchlist = Watchlist::parse("1Sanctioned", "sdn".to_string());
+        let funding = transaction_at("funding", DATE, false, &[("1Sanctioned", 5.0)], &[("1Middle", 4.9)]);
+        let mut spending = transaction_at("spending", DATE + 600, false, &[], &[("1Receiver", 4.8)]);
+        spending.transaction_amounts_mut().insert(0, spend_of("funding", 0, "1Middle", "spending", 4.9, DATE + 600));
+        let blocks = [block(vec![funding, spending])];
+
+        let alerts = watchlist.screen_blocks(&blocks);
+
+        assert_eq!(alerts.len(), 3);
+        assert_eq!(alerts[2].transaction_hash(), &"spending".to_string());
+        assert_eq!(alerts[2].address_hash(), &"1Receiver".to_string());
+        assert_eq!(alerts[2].exposure(), Exposure::OneHop);
+        assert_eq!(alerts[2].listed_address(), &"1Sanctioned".to_string());
+    }
+
+    #[test]
+    fn listed_funding_test() {
+        let watchlist = Watchlist::parse("1Sanctioned", "sdn".to_string());
+        let funding = transaction_at("funding", DATE, false, &[("1Sanctioned", 5.0)], &[("1Middle", 4.9)]);
+        let clean = transaction(&[("1Payer", 2.0)], &[("1Receiver", 1.5)]);
+        let mut spending = transaction_at("spending", DATE + 600, false, &[], &[("1Receiver", 4.8)]);
+        spending.transaction_amounts_mut().insert(0, spend_of("funding", 0, "1Middle", "spending", 4.9, DATE + 600));
+
+        // Only the transaction the listed address paid into is remembered between blocks
+        let mut listed_funding = ListedFunding::new();
+        watchlist.screen_block(&mut listed_funding, &block(vec![funding, clean]));
+        assert_eq!(listed_funding.len(), 1);
+        assert_eq!(listed_funding.senders("funding"), Some(&vec!["1Sanctioned".to_string()]));
+
+        let alerts = watchlist.screen_block(&mut listed_funding, &block(vec![spending]));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].exposure(), Exposure::OneHop);
+    }
+
+    #[test]
+    fn clean_block_test() {
+        let watchlist = Watchlist::parse("1Sanctioned", "sdn".to_string());
+        let blocks = [block(vec![transaction(&[("1Payer", 2.0)], &[("1Receiver", 1.5)])])];
+
+        assert!(watchlist.screen_blocks(&blocks).is_empty());
+    }
+}
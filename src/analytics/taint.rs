@@ -0,0 +1,387 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::analytics::index::TransactionIndex;
+use crate::common::transaction::{Transaction, TransactionAmount};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaintPolicy {
+    Poison,  // any tainted input taints every output in full
+    Haircut, // outputs are tainted in proportion to the tainted share of the inputs
+    Fifo,    // coins leave in the order they came in
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceDirection {
+    Forward,  // where did the funds go
+    Backward, // where did the funds come from
+}
+
+pub struct TaintTracer<'a> {
+    index: &'a TransactionIndex,
+    policy: TaintPolicy,
+    max_depth: usize,
+    min_value: f64
+}
+
+// Taint entering a transaction on one side, at a position in that side's amounts
+struct Step {
+    transaction_hash: String,
+    position: usize,
+    taint: f64,
+    depth: usize
+}
+
+impl<'a> TaintTracer<'a> {
+    pub fn new(index: &'a TransactionIndex, policy: TaintPolicy) -> Self {
+        TaintTracer { index, policy, max_depth: 10, min_value: 0.0 }
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Traced values at or below this are dropped
+    pub fn set_min_value(&mut self, min_value: f64) -> &mut Self {
+        self.min_value = min_value;
+        self
+    }
+
+    pub fn trace(&self, start: &TransactionAmount, direction: TraceDirection) -> TaintGraph {
+        let mut graph = TaintGraphBuilder::default();
+        let mut queue: VecDeque<Step> = VecDeque::new();
+        let taint = start.amount().abs();
+
+        let transaction = match self.index.get(start.transaction_hash()) {
+            Some(transaction) => transaction,
+            None => return graph.build()
+        };
+
+        match (direction, start.is_input()) {
+            (TraceDirection::Forward, false) => {
+                graph.record(transaction, start, false, start.index(), taint, 0);
+                self.follow_spender(transaction.hash(), start, taint, 1, &mut graph, &mut queue);
+            },
+            (TraceDirection::Forward, true) => {
+                if let Some(position) = position_of(transaction, start, true) {
+                    queue.push_back(Step { transaction_hash: transaction.hash().clone(), position, taint, depth: 0 });
+                }
+            },
+            (TraceDirection::Backward, true) => {
+                let position = position_of(transaction, start, true).unwrap_or(0);
+                graph.record(transaction, start, true, position as i32, taint, 0);
+                self.follow_funding(transaction.hash(), start, taint, 1, &mut graph, &mut queue);
+            },
+            (TraceDirection::Backward, false) => {
+                if let Some(position) = position_of(transaction, start, false) {
+                    queue.push_back(Step { transaction_hash: transaction.hash().clone(), position, taint, depth: 0 });
+                }
+            },
+        }
+
+        while let Some(step) = queue.pop_front() {
+            let transaction = match self.index.get(&step.transaction_hash) {
+                Some(transaction) => transaction,
+                None => continue
+            };
+            let inputs: Vec<&TransactionAmount> = transaction.transaction_amounts().iter().filter(|a| a.is_input()).collect();
+            let outputs: Vec<&TransactionAmount> = transaction.transaction_amounts().iter().filter(|a| a.is_output()).collect();
+            let (sources, targets) = match direction {
+                TraceDirection::Forward => (&inputs, &outputs),
+                TraceDirection::Backward => (&outputs, &inputs),
+            };
+
+            let mut taints = vec![0.0; sources.len()];
+            if step.position >= taints.len() {
+                continue
+            }
+            taints[step.position] = step.taint;
+            let source_values: Vec<f64> = sources.iter().map(|a| a.amount().abs()).collect();
+            let target_values: Vec<f64> = targets.iter().map(|a| a.amount().abs()).collect();
+            let distributed = distribute(self.policy, &source_values, &taints, &target_values);
+
+            for (position, (target, traced)) in targets.iter().zip(distributed).enumerate() {
+                if traced <= self.min_value {
+                    continue
+                }
+                let is_input = direction == TraceDirection::Backward;
+                let index = if is_input { position as i32 } else { target.index() };
+                let added = graph.record(transaction, target, is_input, index, traced, step.depth);
+                if added <= self.min_value {
+                    continue
+                }
+                match direction {
+                    TraceDirection::Forward => self.follow_spender(transaction.hash(), target, added, step.depth + 1, &mut graph, &mut queue),
+                    TraceDirection::Backward => self.follow_funding(transaction.hash(), target, added, step.depth + 1, &mut graph, &mut queue),
+                }
+            }
+        }
+
+        graph.build()
+    }
+
+    // Private Methods:
+    fn follow_spender(&self, from: &str, output: &TransactionAmount, taint: f64, depth: usize, graph: &mut TaintGraphBuilder, queue: &mut VecDeque<Step>) {
+        if depth > self.max_depth {
+            return
+        }
+        let spender = match self.index.spender(from, output.index()) {
+            Some(spender) => spender,
+            None => return
+        };
+        let position = spender.transaction_amounts().iter()
+            .filter(|a| a.is_input())
+            .position(|a| a.vin_hash().as_deref() == Some(from) && a.vin_index() == output.index());
+        if let Some(position) = position {
+            graph.edge(from, spender.hash(), taint);
+            queue.push_back(Step { transaction_hash: spender.hash().clone(), position, taint, depth });
+        }
+    }
+
+    fn follow_funding(&self, to: &str, input: &TransactionAmount, taint: f64, depth: usize, graph: &mut TaintGraphBuilder, queue: &mut VecDeque<Step>) {
+        if depth > self.max_depth {
+            return
+        }
+        let funding = match input.vin_hash().as_ref().and_then(|hash| self.index.get(hash)) {
+            Some(funding) => funding,
+            None => return
+        };
+        let position = funding.transaction_amounts().iter()
+            .filter(|a| a.is_output())
+            .position(|a| a.index() == input.vin_index());
+        if let Some(position) = position {
+            graph.edge(funding.hash(), to, taint);
+            queue.push_back(Step { transaction_hash: funding.hash().clone(), position, taint, depth });
+        }
+    }
+}
+
+fn position_of(transaction: &Transaction, amount: &TransactionAmount, is_input: bool) -> Option<usize> {
+    transaction.transaction_amounts().iter()
+        .filter(|a| a.is_input() == is_input)
+        .position(|a| a.index() == amount.index() && a.vin_hash() == amount.vin_hash() && a.vin_index() == amount.vin_index())
+}
+
+// Spreads the taint on `sources` over `targets` according to the policy
+fn distribute(policy: TaintPolicy, sources: &[f64], taints: &[f64], targets: &[f64]) -> Vec<f64> {
+    let tainted: f64 = taints.iter().sum();
+    match policy {
+        TaintPolicy::Poison => {
+            if tainted > 0.0 { targets.to_vec() } else { vec![0.0; targets.len()] }
+        },
+        TaintPolicy::Haircut => {
+            let total: f64 = sources.iter().sum();
+            let share = if total > 0.0 { (tainted / total).min(1.0) } else { 0.0 };
+            targets.iter().map(|t| t * share).collect()
+        },
+        TaintPolicy::Fifo => {
+            // Tainted coins sit at the front of each source's range
+            let mut ranges = vec![];
+            let mut offset = 0.0;
+            for (value, taint) in sources.iter().zip(taints) {
+                if *taint > 0.0 {
+                    ranges.push((offset, offset + taint.min(*value)));
+                }
+                offset += value;
+            }
+            let mut offset = 0.0;
+            targets.iter()
+                .map(|value| {
+                    let (start, end) = (offset, offset + value);
+                    offset = end;
+                    ranges.iter()
+                        .map(|(from, to)| (end.min(*to) - start.max(*from)).max(0.0))
+                        .sum()
+                })
+                .collect()
+        },
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct TracedAmount {
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get = "pub")]
+    address_hash: String,
+
+    // Output index, or the input's position for inputs
+    #[getset(get_copy = "pub")]
+    index: i32,
+
+    #[getset(get_copy = "pub")]
+    is_input: bool,
+
+    #[getset(get_copy = "pub")]
+    amount: f64,
+
+    #[getset(get_copy = "pub")]
+    traced: f64,
+
+    #[getset(get_copy = "pub")]
+    depth: usize
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct TaintEdge {
+    #[getset(get = "pub")]
+    from: String,
+
+    #[getset(get = "pub")]
+    to: String,
+
+    #[getset(get_copy = "pub")]
+    amount: f64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, Getters)]
+#[getset(get = "pub")]
+pub struct TaintGraph {
+    transactions: BTreeSet<String>,
+    addresses: BTreeMap<String, f64>,
+    amounts: Vec<TracedAmount>,
+    edges: Vec<TaintEdge>
+}
+
+impl TaintGraph {
+    pub fn traced_total(&self) -> f64 {
+        self.addresses.values().sum()
+    }
+}
+
+#[derive(Default)]
+struct TaintGraphBuilder {
+    amounts: BTreeMap<(String, bool, i32), TracedAmount>,
+    edges: BTreeMap<(String, String), f64>
+}
+
+impl TaintGraphBuilder {
+    // Adds taint to an amount, capped at its value. Returns how much was actually added.
+    fn record(&mut self, transaction: &Transaction, amount: &TransactionAmount, is_input: bool, index: i32, traced: f64, depth: usize) -> f64 {
+        let entry = self.amounts.entry((transaction.hash().clone(), is_input, index)).or_insert(TracedAmount {
+            transaction_hash: transaction.hash().clone(),
+            address_hash: amount.address_hash().clone(),
+            index,
+            is_input,
+            amount: amount.amount().abs(),
+            traced: 0.0,
+            depth
+        });
+        let before = entry.traced;
+        entry.traced = (entry.traced + traced).min(entry.amount);
+        entry.depth = entry.depth.min(depth);
+        entry.traced - before
+    }
+
+    fn edge(&mut self, from: &str, to: &str, amount: f64) {
+        *self.edges.entry((from.to_string(), to.to_string())).or_default() += amount;
+    }
+
+    fn build(self) -> TaintGraph {
+        let mut graph = TaintGraph::default();
+        for amount in self.amounts.into_values() {
+            graph.transactions.insert(amount.transaction_hash.clone());
+            *graph.addresses.entry(amount.address_hash.clone()).or_default() += amount.traced;
+            graph.amounts.push(amount);
+        }
+        graph.edges = self.edges.into_iter()
+            .map(|((from, to), amount)| {
+                graph.transactions.insert(from.clone());
+                graph.transactions.insert(to.clone());
+                TaintEdge { from, to, amount }
+            })
+            .collect();
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::index::TransactionIndex;
+    use crate::analytics::taint::*;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::spend_of;
+
+    // funding pays alice 6 and carol 4; spend merges both into outputs of 7 (bob) and 3 (dave)
+    fn index() -> TransactionIndex {
+        let mut funding = Transaction::new("funding".to_string(), false, 100, "blocky_hash".to_string(), 1);
+        funding.set_transaction_amounts(vec![
+            TransactionAmount::new(6.0, "alice".to_string(), "funding".to_string(), 100, 0),
+            TransactionAmount::new(4.0, "carol".to_string(), "funding".to_string(), 100, 1),
+        ]);
+
+        let mut spend = Transaction::new("spend".to_string(), false, 200, "blocky_hash".to_string(), 2);
+        spend.set_transaction_amounts(vec![
+            spend_of("funding", 0, "alice", "spend", 6.0, 200),
+            spend_of("funding", 1, "carol", "spend", 4.0, 200),
+            TransactionAmount::new(7.0, "bob".to_string(), "spend".to_string(), 200, 0),
+            TransactionAmount::new(3.0, "dave".to_string(), "spend".to_string(), 200, 1),
+        ]);
+
+        let mut index = TransactionIndex::new();
+        index.insert_transaction(funding).insert_transaction(spend);
+        index
+    }
+
+    fn carol_output(index: &TransactionIndex) -> TransactionAmount {
+        index.output("funding", 1).unwrap().clone()
+    }
+
+    #[test]
+    fn forward_poison_test() {
+        let index = index();
+        let graph = TaintTracer::new(&index, TaintPolicy::Poison).trace(&carol_output(&index), TraceDirection::Forward);
+
+        assert_eq!(graph.addresses().get("bob"), Some(&7.0));
+        assert_eq!(graph.addresses().get("dave"), Some(&3.0));
+        assert_eq!(graph.transactions().len(), 2);
+        assert_eq!(graph.edges()[0].from(), &"funding".to_string());
+    }
+
+    #[test]
+    fn forward_haircut_test() {
+        let index = index();
+        let graph = TaintTracer::new(&index, TaintPolicy::Haircut).trace(&carol_output(&index), TraceDirection::Forward);
+
+        assert!((graph.addresses()["bob"] - 2.8).abs() < 1e-9);
+        assert!((graph.addresses()["dave"] - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn forward_fifo_test() {
+        let index = index();
+        let graph = TaintTracer::new(&index, TaintPolicy::Fifo).trace(&carol_output(&index), TraceDirection::Forward);
+
+        // carol's coins occupy 6..10 of the inputs, bob gets 0..7 and dave 7..10
+        assert_eq!(graph.addresses()["bob"], 1.0);
+        assert_eq!(graph.addresses()["dave"], 3.0);
+    }
+
+    #[test]
+    fn backward_trace_test() {
+        let index = index();
+        let bob = index.output("spend", 0).unwrap().clone();
+        let graph = TaintTracer::new(&index, TaintPolicy::Fifo).trace(&bob, TraceDirection::Backward);
+
+        assert_eq!(graph.addresses()["alice"], 6.0);
+        assert_eq!(graph.addresses()["carol"], 1.0);
+        assert!(graph.transactions().contains("funding"));
+    }
+
+    #[test]
+    fn depth_and_value_limits_test() {
+        let index = index();
+        let mut tracer = TaintTracer::new(&index, TaintPolicy::Haircut);
+        tracer.set_max_depth(0);
+        let graph = tracer.trace(&carol_output(&index), TraceDirection::Forward);
+        assert_eq!(graph.transactions().len(), 1);
+
+        tracer.set_max_depth(10).set_min_value(2.0);
+        let graph = tracer.trace(&carol_output(&index), TraceDirection::Forward);
+        assert!(graph.addresses().contains_key("bob"));
+        assert!(!graph.addresses().contains_key("dave"));
+    }
+}
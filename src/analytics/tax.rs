@@ -0,0 +1,406 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use easy_error::bail;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use crate::analytics::price::PriceSeries;
+use crate::common::address::Address;
+use crate::common::blockchain::BlockChainNames;
+use crate::common::period::Period;
+use crate::common::transaction::{to_satoshis, TransactionAmount, SATOSHIS_PER_COIN};
+use crate::common::wallet::Wallet;
+
+const LONG_TERM: i64 = 365 * 86400;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LotMethod {
+    Fifo,
+    Lifo,
+    Hifo,       // highest cost basis first
+    SpecificId, // lots chosen per disposal, remaining amount falls back to FIFO
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct TaxLot {
+    // Transaction the coins were acquired in
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get_copy = "pub")]
+    acquired: i64,
+
+    #[getset(get_copy = "pub")]
+    amount: f64, // still held
+
+    #[getset(get_copy = "pub")]
+    cost_per_coin: f64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct Disposal {
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get = "pub")]
+    lot_transaction_hash: String,
+
+    #[getset(get_copy = "pub")]
+    acquired: i64,
+
+    #[getset(get_copy = "pub")]
+    disposed: i64,
+
+    #[getset(get_copy = "pub")]
+    amount: f64,
+
+    #[getset(get_copy = "pub")]
+    cost_basis: f64,
+
+    #[getset(get_copy = "pub")]
+    proceeds: f64,
+
+    #[getset(get_copy = "pub")]
+    gain: f64
+}
+
+impl Disposal {
+    pub fn is_long_term(&self) -> bool {
+        self.disposed - self.acquired > LONG_TERM
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, Getters, CopyGetters)]
+pub struct YearSummary {
+    #[getset(get = "pub")]
+    year: String,
+
+    #[getset(get_copy = "pub")]
+    disposal_count: i64,
+
+    #[getset(get_copy = "pub")]
+    proceeds: f64,
+
+    #[getset(get_copy = "pub")]
+    cost_basis: f64,
+
+    #[getset(get_copy = "pub")]
+    short_term_gain: f64,
+
+    #[getset(get_copy = "pub")]
+    long_term_gain: f64
+}
+
+impl YearSummary {
+    pub fn gain(&self) -> f64 {
+        self.short_term_gain + self.long_term_gain
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LotTracker {
+    method: LotMethod,
+    lots: Vec<TaxLot>,
+    disposals: Vec<Disposal>,
+    selections: HashMap<String, Vec<String>> // disposing transaction -> acquiring transactions
+}
+
+impl LotTracker {
+    pub fn new(method: LotMethod) -> Self {
+        LotTracker { method, lots: vec![], disposals: vec![], selections: HashMap::new() }
+    }
+
+    pub fn from_address(address: &Address, series: &PriceSeries, method: LotMethod) -> Result<Self, Box<dyn Error>> {
+        let mut tracker = LotTracker::new(method);
+        tracker.process(address.history().as_vec(), series)?;
+        Ok(tracker)
+    }
+
+    // Uses the wallet's combined history, so coins moved between member addresses net out
+    pub fn from_wallet(wallet: &Wallet, blockchain_name: &BlockChainNames, series: &PriceSeries, method: LotMethod) -> Result<Self, Box<dyn Error>> {
+        let mut tracker = LotTracker::new(method);
+        tracker.process(&wallet.history(blockchain_name), series)?;
+        Ok(tracker)
+    }
+
+    // Lots (by acquiring transaction hash) to consume first for a disposal under SpecificId
+    pub fn select_lots(&mut self, disposal_hash: String, lot_hashes: Vec<String>) -> &mut Self {
+        self.selections.insert(disposal_hash, lot_hashes);
+        self
+    }
+
+    // Amounts are netted per transaction, so change sent back to the same address (or
+    // wallet) doesn't count as a disposal and reacquisition. Transactions sharing a date
+    // (e.g. one block) run acquisitions first, then in the order they appear in `amounts`.
+    // The batch runs on a copy, so an error leaves the tracker as it was before the call.
+    pub fn process(&mut self, amounts: &[TransactionAmount], series: &PriceSeries) -> Result<&mut Self, Box<dyn Error>> {
+        let mut transactions: Vec<(i64, &String, i64)> = vec![];
+        let mut positions: HashMap<(i64, &String), usize> = HashMap::new();
+        for amount in amounts {
+            let position = *positions.entry((amount.date(), amount.transaction_hash())).or_insert_with(|| {
+                transactions.push((amount.date(), amount.transaction_hash(), 0));
+                transactions.len() - 1
+            });
+            transactions[position].2 += to_satoshis(amount.amount());
+        }
+        transactions.sort_by_key(|(date, _, satoshis)| (*date, *satoshis < 0));
+
+        let mut next = self.clone();
+        for (date, hash, satoshis) in transactions {
+            if satoshis == 0 {
+                continue
+            }
+            let price = match series.price_at(date) {
+                Some(price) => price,
+                None => bail!("No {} price for transaction {} at {}", series.ticker(), hash, date),
+            };
+            let amount = satoshis.abs() as f64 / SATOSHIS_PER_COIN;
+            if satoshis > 0 {
+                next.lots.push(TaxLot { transaction_hash: hash.clone(), acquired: date, amount, cost_per_coin: price });
+            } else {
+                next.dispose(hash, date, amount, price)?;
+            }
+        }
+        *self = next;
+        Ok(self)
+    }
+
+    pub fn lots(&self) -> &Vec<TaxLot> {
+        &self.lots
+    }
+
+    pub fn disposals(&self) -> &Vec<Disposal> {
+        &self.disposals
+    }
+
+    pub fn realized_gain(&self) -> f64 {
+        self.disposals.iter().map(|d| d.gain).sum()
+    }
+
+    pub fn summary_by_year(&self) -> Vec<YearSummary> {
+        let mut years: BTreeMap<String, YearSummary> = BTreeMap::new();
+        for disposal in &self.disposals {
+            let year = Period::Year.label(disposal.disposed);
+            let summary = years.entry(year.clone()).or_insert_with(|| YearSummary { year, ..YearSummary::default() });
+            summary.disposal_count += 1;
+            summary.proceeds += disposal.proceeds;
+            summary.cost_basis += disposal.cost_basis;
+            if disposal.is_long_term() {
+                summary.long_term_gain += disposal.gain;
+            } else {
+                summary.short_term_gain += disposal.gain;
+            }
+        }
+        years.into_values().collect()
+    }
+
+    pub fn disposals_csv(&self) -> String {
+        let mut csv = String::from("transaction_hash,lot_transaction_hash,acquired,disposed,amount,cost_basis,proceeds,gain,term\n");
+        for d in &self.disposals {
+            let term = if d.is_long_term() { "long" } else { "short" };
+            csv.push_str(&format!("{},{},{},{},{},{},{},{},{}\n",
+                d.transaction_hash, d.lot_transaction_hash, Period::Day.label(d.acquired), Period::Day.label(d.disposed),
+                d.amount, d.cost_basis, d.proceeds, d.gain, term));
+        }
+        csv
+    }
+
+    pub fn summary_csv(&self) -> String {
+        let mut csv = String::from("year,disposal_count,proceeds,cost_basis,short_term_gain,long_term_gain,gain\n");
+        for s in self.summary_by_year() {
+            csv.push_str(&format!("{},{},{},{},{},{},{}\n",
+                s.year, s.disposal_count, s.proceeds, s.cost_basis, s.short_term_gain, s.long_term_gain, s.gain()));
+        }
+        csv
+    }
+
+    // Private Methods:
+    fn dispose(&mut self, hash: &str, date: i64, amount: f64, price: f64) -> Result<(), Box<dyn Error>> {
+        let held: i64 = self.lots.iter().map(|lot| to_satoshis(lot.amount)).sum();
+        let mut remaining = to_satoshis(amount);
+        if remaining > held {
+            bail!("Transaction {} disposes {} more coins than are held", hash, (remaining - held) as f64 / SATOSHIS_PER_COIN);
+        }
+
+        let mut order: Vec<usize> = (0..self.lots.len()).collect();
+        match self.method {
+            LotMethod::Fifo => {},
+            LotMethod::Lifo => order.reverse(),
+            LotMethod::Hifo => order.sort_by(|a, b| self.lots[*b].cost_per_coin.total_cmp(&self.lots[*a].cost_per_coin)),
+            LotMethod::SpecificId => {
+                let selected = self.selections.get(hash).cloned().unwrap_or_default();
+                order.sort_by_key(|i| selected.iter().position(|s| s == &self.lots[*i].transaction_hash).unwrap_or(usize::MAX));
+            },
+        }
+
+        for i in order {
+            if remaining == 0 {
+                break
+            }
+            let lot = &mut self.lots[i];
+            let taken = remaining.min(to_satoshis(lot.amount));
+            if taken == 0 {
+                continue
+            }
+            let coins = taken as f64 / SATOSHIS_PER_COIN;
+            lot.amount = (to_satoshis(lot.amount) - taken) as f64 / SATOSHIS_PER_COIN;
+            remaining -= taken;
+
+            let cost_basis = coins * lot.cost_per_coin;
+            let proceeds = coins * price;
+            self.disposals.push(Disposal {
+                transaction_hash: hash.to_string(),
+                lot_transaction_hash: lot.transaction_hash.clone(),
+                acquired: lot.acquired,
+                disposed: date,
+                amount: coins,
+                cost_basis,
+                proceeds,
+                gain: proceeds - cost_basis
+            });
+        }
+        self.lots.retain(|lot| to_satoshis(lot.amount) > 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::price::{Interpolation, PriceSeries};
+    use crate::analytics::tax::*;
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::transaction::TransactionAmount;
+    use crate::common::wallet::Wallet;
+    use crate::test_fixtures::{address, input};
+
+    const DAY: i64 = 86400;
+    const JAN_1: i64 = 1672531200; // 2023-01-01
+
+    fn series() -> PriceSeries {
+        let mut series = PriceSeries::new("BTC".to_string(), Interpolation::Previous);
+        series.insert(JAN_1, 10.0).insert(JAN_1 + DAY, 30.0).insert(JAN_1 + 2 * DAY, 20.0).insert(JAN_1 + 400 * DAY, 50.0);
+        series
+    }
+
+    // Buys 1 at 10 and 1 at 30, then sells 1.5 at 20 with 0.5 coming back as change
+    fn amounts() -> Vec<TransactionAmount> {
+        vec![
+            TransactionAmount::new(1.0, "alice".to_string(), "buy1".to_string(), JAN_1, 0),
+            TransactionAmount::new(1.0, "alice".to_string(), "buy2".to_string(), JAN_1 + DAY, 0),
+            TransactionAmount::new(-2.0, "alice".to_string(), "sell".to_string(), JAN_1 + 2 * DAY, -1),
+            TransactionAmount::new(0.5, "alice".to_string(), "sell".to_string(), JAN_1 + 2 * DAY, 1),
+        ]
+    }
+
+    fn tracker(method: LotMethod) -> LotTracker {
+        let mut tracker = LotTracker::new(method);
+        tracker.process(&amounts(), &series()).unwrap();
+        tracker
+    }
+
+    #[test]
+    fn methods_test() {
+        let fifo = tracker(LotMethod::Fifo);
+        assert_eq!(fifo.disposals().len(), 2);
+        assert_eq!(fifo.disposals()[0].lot_transaction_hash(), &"buy1".to_string());
+        assert_eq!(fifo.realized_gain(), 10.0 - 5.0);
+        assert_eq!(fifo.lots()[0].amount(), 0.5);
+
+        let lifo = tracker(LotMethod::Lifo);
+        assert_eq!(lifo.disposals()[0].lot_transaction_hash(), &"buy2".to_string());
+        assert_eq!(lifo.realized_gain(), -10.0 + 5.0);
+
+        let hifo = tracker(LotMethod::Hifo);
+        assert_eq!(hifo.realized_gain(), lifo.realized_gain());
+
+        let mut specific = LotTracker::new(LotMethod::SpecificId);
+        specific.select_lots("sell".to_string(), vec!["buy2".to_string()]);
+        specific.process(&amounts(), &series()).unwrap();
+        assert_eq!(specific.disposals()[0].lot_transaction_hash(), &"buy2".to_string());
+        assert_eq!(specific.disposals()[1].amount(), 0.5);
+    }
+
+    #[test]
+    fn oversold_and_unpriced_test() {
+        let mut tracker = LotTracker::new(LotMethod::Fifo);
+        let sell = TransactionAmount::new(-1.0, "alice".to_string(), "sell".to_string(), JAN_1, -1);
+        assert!(tracker.process(&[sell], &series()).is_err());
+
+        let early = TransactionAmount::new(1.0, "alice".to_string(), "buy".to_string(), JAN_1 - DAY, 0);
+        assert!(LotTracker::new(LotMethod::Fifo).process(&[early], &series()).is_err());
+
+        // A failed disposal doesn't consume what is held
+        let mut held = LotTracker::new(LotMethod::Fifo);
+        held.process(&amounts()[..2], &series()).unwrap();
+        let oversell = TransactionAmount::new(-3.0, "alice".to_string(), "sell".to_string(), JAN_1 + 2 * DAY, -1);
+        assert!(held.process(&[oversell], &series()).is_err());
+        assert_eq!(held.lots().len(), 2);
+        assert!(held.disposals().is_empty());
+
+        // Nothing from a failing batch is applied, including the transactions before the failure
+        let buy = TransactionAmount::new(1.0, "alice".to_string(), "buy3".to_string(), JAN_1 + DAY, 0);
+        let oversell = TransactionAmount::new(-4.0, "alice".to_string(), "sell".to_string(), JAN_1 + 2 * DAY, -1);
+        assert!(held.process(&[buy, oversell], &series()).is_err());
+        assert_eq!(held.lots().len(), 2);
+    }
+
+    #[test]
+    fn from_wallet_test() {
+        // alice buys 2, sends 1.5 to bob with 0.49 change, then bob sells 1
+        let mut wallet = Wallet::new("wallet_1".to_string(), "Customer".to_string());
+        wallet.add_address(BlockChainNames::Bitcoin, address("alice", vec![
+            TransactionAmount::new(2.0, "alice".to_string(), "buy".to_string(), JAN_1, 0),
+            input("alice", "move", 2.0, JAN_1 + DAY),
+            TransactionAmount::new(0.49, "alice".to_string(), "move".to_string(), JAN_1 + DAY, 1),
+        ]));
+        wallet.add_address(BlockChainNames::Bitcoin, address("bob", vec![
+            TransactionAmount::new(1.5, "bob".to_string(), "move".to_string(), JAN_1 + DAY, 0),
+            input("bob", "sell", 1.5, JAN_1 + 2 * DAY),
+            TransactionAmount::new(0.5, "bob".to_string(), "sell".to_string(), JAN_1 + 2 * DAY, 1),
+        ]));
+
+        // The move only disposes of its 0.01 fee
+        let tracker = LotTracker::from_wallet(&wallet, &BlockChainNames::Bitcoin, &series(), LotMethod::Fifo).unwrap();
+        assert_eq!(tracker.disposals().len(), 2);
+        assert_eq!(tracker.disposals()[0].transaction_hash(), &"move".to_string());
+        assert_eq!(tracker.disposals()[0].amount(), 0.01);
+        assert_eq!(tracker.disposals()[1].amount(), 1.0);
+        assert_eq!(tracker.lots()[0].amount(), 0.99);
+    }
+
+    #[test]
+    fn same_date_receive_before_spend_test() {
+        // "a_spend" sorts before "b_receive" by hash but spends the coins it brings in
+        let amounts = vec![
+            TransactionAmount::new(-1.0, "alice".to_string(), "a_spend".to_string(), JAN_1, -1),
+            TransactionAmount::new(1.0, "alice".to_string(), "b_receive".to_string(), JAN_1, 0),
+        ];
+        let mut tracker = LotTracker::new(LotMethod::Fifo);
+        tracker.process(&amounts, &series()).unwrap();
+
+        assert_eq!(tracker.disposals().len(), 1);
+        assert_eq!(tracker.disposals()[0].lot_transaction_hash(), &"b_receive".to_string());
+        assert!(tracker.lots().is_empty());
+    }
+
+    #[test]
+    fn summary_and_csv_test() {
+        let mut amounts = amounts();
+        amounts.push(TransactionAmount::new(-0.5, "alice".to_string(), "later".to_string(), JAN_1 + 400 * DAY, -1));
+        let mut tracker = LotTracker::new(LotMethod::Fifo);
+        tracker.process(&amounts, &series()).unwrap();
+
+        let summary = tracker.summary_by_year();
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].year(), &"2023".to_string());
+        assert_eq!(summary[0].short_term_gain(), 5.0);
+        assert_eq!(summary[1].year(), &"2024".to_string());
+        assert_eq!(summary[1].long_term_gain(), 10.0);
+
+        let csv = tracker.disposals_csv();
+        assert!(csv.starts_with("transaction_hash,lot_transaction_hash,acquired"));
+        assert!(csv.contains("later,buy2,2023-01-02,2024-02-05,0.5,15,25,10,long"));
+        assert!(tracker.summary_csv().contains("2023,2,30,25,5,0,5"));
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use getset::CopyGetters;
+use std::collections::HashSet;
+
+use crate::analytics::change::{ChangeContext, ChangeDetector};
+use crate::common::block::Block;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct ThroughputStats {
+    transaction_count: i64,
+    transaction_amount_count: i64,
+    transferred_volume: f64, // non coinbase outputs, less detected change
+    adjusted_volume: f64,    // transferred volume less outputs paying back to an input address
+    velocity: f64,           // adjusted volume over supply
+    median_transfer: f64,
+    mean_transfer: f64,
+    tps: f64
+}
+
+impl ThroughputStats {
+    // Blocks before the window are only used to warm up the change detection context
+    pub fn for_window(blocks: &[Block], date_start: i64, date_end: i64, supply: f64, detector: &ChangeDetector) -> Self {
+        let mut stats = ThroughputStats::default();
+        let mut context = ChangeContext::new();
+        let mut transfers: Vec<f64> = vec![];
+
+        let mut sorted: Vec<&Block> = blocks.iter().filter(|b| b.date() <= date_end).collect();
+        sorted.sort_by_key(|block| block.height());
+        for block in sorted {
+            for transaction in block.transactions() {
+                if transaction.date() < date_start {
+                    context.observe_transaction(transaction);
+                    continue
+                }
+                stats.transaction_count += 1;
+                stats.transaction_amount_count += transaction.transaction_amounts().len() as i64;
+
+                if !transaction.is_coinbase() {
+                    let change_index = detector.detect(transaction, &context).change_index();
+                    let input_addresses: HashSet<&String> = transaction.transaction_amounts().iter()
+                        .filter(|a| a.is_input())
+                        .map(|a| a.address_hash())
+                        .collect();
+                    for output in transaction.transaction_amounts().iter().filter(|a| a.is_output()) {
+                        if Some(output.index()) == change_index {
+                            continue
+                        }
+                        stats.transferred_volume += output.amount();
+                        if !input_addresses.contains(output.address_hash()) {
+                            stats.adjusted_volume += output.amount();
+                            transfers.push(output.amount());
+                        }
+                    }
+                }
+                context.observe_transaction(transaction);
+            }
+        }
+
+        if supply > 0.0 {
+            stats.velocity = stats.adjusted_volume / supply;
+        }
+        if !transfers.is_empty() {
+            transfers.sort_unstable_by(|a, b| a.total_cmp(b));
+            let middle = transfers.len() / 2;
+            stats.median_transfer = if transfers.len() % 2 == 1 {
+                transfers[middle]
+            } else {
+                (transfers[middle - 1] + transfers[middle]) / 2.0
+            };
+            stats.mean_transfer = transfers.iter().sum::<f64>() / transfers.len() as f64;
+        }
+        stats.tps = stats.transaction_count as f64 / (date_end - date_start).max(1) as f64;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::change::ChangeDetector;
+    use crate::analytics::throughput::*;
+    use crate::common::block::Block;
+    use crate::common::transaction::to_satoshis;
+    use crate::test_fixtures::transaction_at;
+
+    #[test]
+    fn window_test() {
+        let mut early = Block::new("early".to_string(), 50, 1);
+        early.set_transactions(vec![transaction_at("seen", 50, false, &[("alice", 11.0)], &[("bob", 10.0), ("carol", 1.0)])]);
+
+        let mut block = Block::new("block".to_string(), 150, 2);
+        block.set_transactions(vec![
+            transaction_at("coinbase", 150, true, &[], &[("miner", 6.25)]),
+            // Round payment to a known address, odd amount to a fresh one looks like change
+            transaction_at("payment", 150, false, &[("bob", 10.0)], &[("carol", 2.0), ("fresh", 7.99)]),
+            transaction_at("self", 150, false, &[("dave", 3.0)], &[("dave", 2.99)]),
+        ]);
+        let mut carol = Block::new("carol".to_string(), 160, 3);
+        carol.set_transactions(vec![transaction_at("carol", 160, false, &[("erin", 5.0)], &[("carol", 4.0)])]);
+
+        let stats = ThroughputStats::for_window(&[early, block, carol], 100, 200, 100.0, &ChangeDetector::new());
+
+        assert_eq!(stats.transaction_count(), 4);
+        assert_eq!(stats.transaction_amount_count(), 8);
+        // Summed floats, so amounts are compared in satoshis and ratios with a tolerance
+        assert_eq!(to_satoshis(stats.transferred_volume()), to_satoshis(8.99));
+        assert_eq!(to_satoshis(stats.adjusted_volume()), to_satoshis(6.0));
+        assert!((stats.velocity() - 0.06).abs() < 1e-9);
+        assert_eq!(to_satoshis(stats.median_transfer()), to_satoshis(3.0));
+        assert_eq!(to_satoshis(stats.mean_transfer()), to_satoshis(3.0));
+        assert!((stats.tps() - 0.04).abs() < 1e-9);
+    }
+}
@@ -10,7 +10,10 @@ use devii::devii::DeviiTrait;
 use std::hash::{Hash, Hasher};
 
 
-use crate::common::transaction::{TransactionAmount};
+use crate::common::transaction::{Transaction, TransactionAmount, to_satoshis};
+use crate::common::history::TransactionHistory;
+use crate::common::blockchain::BlockChainNames;
+use crate::common::period::Period;
 
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug, Clone, NamedType, Default, Getters, CopyGetters, Setters)]
@@ -36,8 +39,7 @@ pub struct Address {
     #[getset(get_copy = "pub", set = "pub")]
     needs_update: bool,
 
-    #[getset(get = "pub", set = "pub")]
-    transactions: Vec<TransactionAmount> 
+    transactions: TransactionHistory
 
 }
 
@@ -63,7 +65,7 @@ impl Address {
             is_miner: false,
             first_transaction: 0,
             last_updated: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-            transactions: Vec::new(),
+            transactions: TransactionHistory::new(),
             needs_update: true
         }
     }
@@ -75,33 +77,273 @@ impl Address {
 
         self.coin_total += tx_amount.amount();
 
-        if self.transactions.len() == 0 {
-            self.first_transaction = tx_amount.date();
+        self.transactions.insert(tx_amount);
+
+        self.first_transaction = self.transactions.first_date().unwrap_or(0);
+        self.last_transaction = self.transactions.last_date().unwrap_or(0);
+
+        self
+    }
+
+    // Ingests this address's amounts from a transaction, stamping them with the
+    // transaction's block height. Coinbase outputs go through add_coinbase_amount.
+    pub fn add_transaction(&mut self, transaction: &Transaction) -> &mut Self {
+        let amounts: Vec<TransactionAmount> = transaction.transaction_amounts().iter()
+            .filter(|a| a.address_hash() == &self.hash)
+            .cloned()
+            .collect();
+        for mut tx_amount in amounts {
+            if transaction.is_coinbase() && tx_amount.is_output() {
+                self.add_coinbase_amount(tx_amount, transaction.block_height());
+            } else {
+                tx_amount.set_block_height(Some(transaction.block_height()));
+                self.add_transaction_amount(tx_amount, false);
+            }
         }
-        
-        self.transactions.push(tx_amount);
+        self
+    }
 
-        self.last_transaction = self.get_latest_transaction();
+    pub fn transactions(&self) -> &Vec<TransactionAmount> {
+        self.transactions.as_vec()
+    }
 
+    pub fn set_transactions(&mut self, transactions: Vec<TransactionAmount>) -> &mut Self {
+        self.transactions = TransactionHistory::from(transactions);
         self
     }
 
-    // Private Methods:
-    fn get_latest_transaction(&mut self) -> i64 {
-        if self.transactions.len() < 1 {
-            return 0 
-        } else {
-            self.transactions.sort_by(|a, b| b.date().cmp(&a.date()));
-            let mut iter = self.transactions.iter();
-            return iter.next().unwrap().date();
+    pub fn history(&self) -> &TransactionHistory {
+        &self.transactions
+    }
+
+    pub fn add_coinbase_amount(&mut self, mut tx_amount: TransactionAmount, block_height: i64) -> &mut Self {
+        tx_amount.set_block_height(Some(block_height));
+        tx_amount.set_is_coinbase(true);
+
+        self.add_transaction_amount(tx_amount, true)
+    }
+
+    pub fn immature_balance(&self, tip_height: i64, chain: &BlockChainNames) -> f64 {
+        self.transactions.iter()
+            .filter(|a| a.is_coinbase())
+            .filter(|a| match a.block_height() {
+                Some(height) => !chain.coinbase_is_mature(height, tip_height),
+                None => false
+            })
+            .map(|a| a.amount())
+            .sum()
+    }
+
+    pub fn mature_balance(&self, tip_height: i64, chain: &BlockChainNames) -> f64 {
+        self.coin_total - self.immature_balance(tip_height, chain)
+    }
+
+    // Rewards that became spendable as the tip advanced from `from_height` to `to_height`
+    pub fn matured_between(&self, from_height: i64, to_height: i64, chain: &BlockChainNames) -> f64 {
+        self.immature_balance(from_height, chain) - self.immature_balance(to_height, chain)
+    }
+
+    // Folds new amounts into the stored totals. Full mode first rebuilds the totals
+    // from the existing transactions and reports where the stored values had drifted.
+    pub fn recompute(&mut self, new_amounts: Vec<TransactionAmount>, mode: RecomputeMode) -> AddressDrift {
+        let mut drift = AddressDrift::default();
+
+        if mode == RecomputeMode::Full {
+            let coin_total: f64 = self.transactions.iter().map(|a| a.amount()).sum();
+            let first_transaction = self.transactions.first_date().unwrap_or(0);
+            let last_transaction = self.transactions.last_date().unwrap_or(0);
+            let is_miner = self.transactions.iter().any(|a| a.is_coinbase());
+
+            if to_satoshis(coin_total) != to_satoshis(self.coin_total) {
+                drift.coin_total = Some((self.coin_total, coin_total));
+            }
+            if first_transaction != self.first_transaction {
+                drift.first_transaction = Some((self.first_transaction, first_transaction));
+            }
+            if last_transaction != self.last_transaction {
+                drift.last_transaction = Some((self.last_transaction, last_transaction));
+            }
+            if is_miner != self.is_miner {
+                drift.is_miner = Some((self.is_miner, is_miner));
+            }
+
+            self.coin_total = coin_total;
+            self.first_transaction = first_transaction;
+            self.last_transaction = last_transaction;
+            self.is_miner = is_miner;
+        }
+
+        drift.applied = new_amounts.len();
+        for tx_amount in new_amounts {
+            if self.transactions.is_empty() || tx_amount.date() < self.first_transaction {
+                self.first_transaction = tx_amount.date();
+            }
+            if tx_amount.date() > self.last_transaction {
+                self.last_transaction = tx_amount.date();
+            }
+            if tx_amount.is_coinbase() {
+                self.is_miner = true;
+            }
+            self.coin_total += tx_amount.amount();
+            self.transactions.insert(tx_amount);
+        }
+
+        self.needs_update = false;
+        self.last_updated = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        drift
+    }
+
+    // Runs a full recompute only if the address is flagged as needing one
+    pub fn refresh(&mut self) -> Option<AddressDrift> {
+        if !self.needs_update {
+            return None
         }
+        Some(self.recompute(vec![], RecomputeMode::Full))
     }
+
+    // Amounts without a block height are left out, add_transaction stamps them on ingestion
+    pub fn balance_at_height(&self, height: i64) -> f64 {
+        self.transactions.iter()
+            .filter(|a| a.block_height().map(|h| h <= height).unwrap_or(false))
+            .map(|a| a.amount())
+            .sum()
+    }
+
+    pub fn balance_at_time(&self, time: i64) -> f64 {
+        self.transactions.until(time).iter()
+            .map(|a| a.amount())
+            .sum()
+    }
+
+    // Closing balance of every period from the first to the last transaction
+    pub fn balance_series(&self, period: Period) -> Vec<BalancePoint> {
+        let (first, last) = match (self.transactions.first_date(), self.transactions.last_date()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return vec![]
+        };
+
+        let mut series = vec![];
+        let mut balance = 0.0;
+        let mut amounts = self.transactions.iter().peekable();
+        for start in period.range(first, last) {
+            let end = period.next(start);
+            while let Some(amount) = amounts.next_if(|a| a.date() < end) {
+                balance += amount.amount();
+            }
+            series.push(BalancePoint { date: start, balance });
+        }
+        series
+    }
+
+    pub fn max_balance(&self) -> Option<BalancePoint> {
+        let mut balance = 0.0;
+        let mut max: Option<BalancePoint> = None;
+        let mut amounts = self.transactions.iter().peekable();
+        while let Some(amount) = amounts.next() {
+            balance += amount.amount();
+            // Only compare once every amount at this timestamp is applied
+            if amounts.peek().map(|next| next.date() == amount.date()).unwrap_or(false) {
+                continue
+            }
+            if max.as_ref().map(|m| balance > m.balance).unwrap_or(true) {
+                max = Some(BalancePoint { date: amount.date(), balance });
+            }
+        }
+        max
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    Account, // Ethereum style accounts
+    Unknown,
+}
+
+impl AddressType {
+    // Best guess from the encoded address alone
+    pub fn from_hash(hash: &str) -> AddressType {
+        let lower = hash.to_lowercase();
+        if lower.starts_with("0x") && hash.len() == 42 {
+            return AddressType::Account
+        }
+        if let Some(cash) = lower.strip_prefix("bitcoincash:") {
+            return match cash.chars().next() {
+                Some('q') => AddressType::P2PKH,
+                Some('p') => AddressType::P2SH,
+                _ => AddressType::Unknown
+            }
+        }
+        for hrp in ["bc1", "tb1", "bcrt1", "ltc1", "tltc1"] {
+            if let Some(data) = lower.strip_prefix(hrp) {
+                return match (data.chars().next(), data.len()) {
+                    (Some('q'), 39) => AddressType::P2WPKH,
+                    (Some('q'), 59) => AddressType::P2WSH,
+                    (Some('p'), 59) => AddressType::P2TR,
+                    _ => AddressType::Unknown
+                }
+            }
+        }
+        match hash.chars().next() {
+            Some('1') | Some('L') | Some('D') | Some('X') | Some('m') | Some('n') => AddressType::P2PKH,
+            Some('3') | Some('M') | Some('A') | Some('9') | Some('7') | Some('2') => AddressType::P2SH,
+            _ => AddressType::Unknown
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecomputeMode {
+    Full,
+    Incremental, // Trusts the stored totals and only applies the new amounts
+}
+
+// Each field holds (stored, recomputed) when the two disagreed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default, CopyGetters)]
+pub struct AddressDrift {
+    #[getset(get_copy = "pub")]
+    coin_total: Option<(f64, f64)>,
+
+    #[getset(get_copy = "pub")]
+    first_transaction: Option<(i64, i64)>,
+
+    #[getset(get_copy = "pub")]
+    last_transaction: Option<(i64, i64)>,
+
+    #[getset(get_copy = "pub")]
+    is_miner: Option<(bool, bool)>,
+
+    #[getset(get_copy = "pub")]
+    applied: usize
+}
+
+impl AddressDrift {
+    pub fn has_drift(&self) -> bool {
+        self.coin_total.is_some()
+            || self.first_transaction.is_some()
+            || self.last_transaction.is_some()
+            || self.is_miner.is_some()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, CopyGetters)]
+pub struct BalancePoint {
+    #[getset(get_copy = "pub")]
+    date: i64,
+
+    #[getset(get_copy = "pub")]
+    balance: f64
 }
 
 impl DeviiTrait for Address {
     fn fetch_fields() -> String {
         format!("{{  hash, last_transaction, coin_total, is_miner, first_transaction, last_updated,
-            transaction_collection {{  amount, address_hash, transaction_hash, date, index, last_updated }}  }}")
+            transaction_collection {{  amount, address_hash, transaction_hash, date, index, last_updated, block_height, is_coinbase, script_sig, witness, sequence }}  }}")
     }
     fn insert_query(&self, param: String) -> String{
         format!("create_address (input: ${} ){{ hash }}", param)
@@ -126,8 +368,11 @@ impl DeviiTrait for Address {
 
 #[cfg(test)]
 mod tests {
-    use crate::common::address::Address;
-    use crate::common::transaction::TransactionAmount;
+    use crate::common::address::{Address, AddressType, RecomputeMode};
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::period::Period;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::test_fixtures::spend_of;
     
 
     #[test]
@@ -153,6 +398,204 @@ mod tests {
         assert_eq!(address.last_transaction(), 987654321);
     }
     
+    #[test]
+    fn coinbase_maturity_test() {
+        let mut address = Address::new("miner_address".to_string());
+        let reward = TransactionAmount::new(50.0, "miner_address".to_string(), "coinbase".to_string(), 123456789, 0);
+        let payment = TransactionAmount::new(1.0, "miner_address".to_string(), "payment".to_string(), 123456790, 0);
+
+        address.add_coinbase_amount(reward, 1000);
+        address.add_transaction_amount(payment, false);
+
+        assert!(address.is_miner());
+        assert_eq!(address.coin_total(), 51.0);
+        assert_eq!(address.immature_balance(1050, &BlockChainNames::Bitcoin), 50.0);
+        assert_eq!(address.mature_balance(1050, &BlockChainNames::Bitcoin), 1.0);
+        assert_eq!(address.mature_balance(1099, &BlockChainNames::Bitcoin), 51.0);
+    }
+
+    #[test]
+    fn miner_payment_is_not_coinbase_test() {
+        let mut address = Address::new("miner_address".to_string());
+        let payment = TransactionAmount::new(1.0, "miner_address".to_string(), "payment".to_string(), 123456790, 0);
+
+        address.add_transaction_amount(payment, true);
+
+        assert!(address.is_miner());
+        assert!(!address.transactions()[0].is_coinbase());
+        assert_eq!(address.immature_balance(1000, &BlockChainNames::Bitcoin), 0.0);
+    }
+
+    #[test]
+    fn matured_between_test() {
+        let mut address = Address::new("miner_address".to_string());
+        let first = TransactionAmount::new(50.0, "miner_address".to_string(), "coinbase_1".to_string(), 123456789, 0);
+        let second = TransactionAmount::new(25.0, "miner_address".to_string(), "coinbase_2".to_string(), 123456790, 0);
+
+        address.add_coinbase_amount(first, 1000);
+        address.add_coinbase_amount(second, 1010);
+
+        assert_eq!(address.matured_between(1040, 1050, &BlockChainNames::Bitcoin), 0.0);
+        assert_eq!(address.matured_between(1050, 1100, &BlockChainNames::Bitcoin), 50.0);
+        assert_eq!(address.immature_balance(1100, &BlockChainNames::Bitcoin), 25.0);
+        assert_eq!(address.matured_between(1100, 1200, &BlockChainNames::Bitcoin), 25.0);
+        assert_eq!(address.immature_balance(1200, &BlockChainNames::Bitcoin), 0.0);
+
+        // The same amounts round-tripped through serde keep their maturity data
+        let json = serde_json::to_string(&address).unwrap();
+        let address: Address = serde_json::from_str(&json).unwrap();
+        assert_eq!(address.immature_balance(1100, &BlockChainNames::Bitcoin), 25.0);
+    }
+
+    fn address_with_history() -> Address {
+        let mut address = Address::new("hashy_address".to_string());
+        let amounts = vec![
+            (10.0, 1667260800, 100), // 2022-11-01
+            (5.0, 1667347200, 110),  // 2022-11-02
+            (-12.0, 1669939200, 150), // 2022-12-02
+            (2.0, 1672617600, 200),  // 2023-01-02
+        ];
+        for (amount, date, height) in amounts {
+            let mut tx_amount = TransactionAmount::new(amount, "hashy_address".to_string(), "transaction_hash".to_string(), date, 0);
+            tx_amount.set_block_height(Some(height));
+            address.add_transaction_amount(tx_amount, false);
+        }
+        address
+    }
+
+    #[test]
+    fn balance_at_test() {
+        let address = address_with_history();
+
+        assert_eq!(address.balance_at_height(99), 0.0);
+        assert_eq!(address.balance_at_height(110), 15.0);
+        assert_eq!(address.balance_at_time(1669939200), 3.0);
+        assert_eq!(address.balance_at_time(1672617600), 5.0);
+    }
+
+    #[test]
+    fn balance_at_height_from_transactions_test() {
+        let mut address = Address::new("hashy_address".to_string());
+        let mut receive = Transaction::new("receive".to_string(), false, 1667260800, "block_100".to_string(), 100);
+        receive.set_transaction_amounts(vec![
+            TransactionAmount::new(10.0, "hashy_address".to_string(), "receive".to_string(), 1667260800, 0),
+            TransactionAmount::new(4.0, "someone_else".to_string(), "receive".to_string(), 1667260800, 1),
+        ]);
+        let mut spend = Transaction::new("spend".to_string(), false, 1667347200, "block_110".to_string(), 110);
+        spend.set_transaction_amounts(vec![
+            spend_of("receive", 0, "hashy_address", "spend", 10.0, 1667347200),
+            TransactionAmount::new(6.0, "hashy_address".to_string(), "spend".to_string(), 1667347200, 0),
+        ]);
+
+        address.add_transaction(&receive).add_transaction(&spend);
+
+        assert_eq!(address.transactions().len(), 3);
+        assert!(!address.is_miner());
+        assert_eq!(address.balance_at_height(99), 0.0);
+        assert_eq!(address.balance_at_height(100), 10.0);
+        assert_eq!(address.balance_at_height(110), 6.0);
+    }
+
+    #[test]
+    fn balance_series_test() {
+        let address = address_with_history();
+        let series = address.balance_series(Period::Month);
+
+        let balances: Vec<f64> = series.iter().map(|p| p.balance()).collect();
+        assert_eq!(balances, vec![15.0, 3.0, 5.0]);
+        assert_eq!(series[0].date(), 1667260800);
+    }
+
+    #[test]
+    fn max_balance_test() {
+        let address = address_with_history();
+        let max = address.max_balance().unwrap();
+
+        assert_eq!(max.balance(), 15.0);
+        assert_eq!(max.date(), 1667347200);
+        assert!(Address::new("empty".to_string()).max_balance().is_none());
+    }
+
+    #[test]
+    fn recompute_full_reports_drift_test() {
+        let mut address = address_with_history();
+        address.set_coin_total(100.0);
+        address.set_first_transaction(0);
+        address.set_needs_update(true);
+
+        let drift = address.refresh().unwrap();
+
+        assert!(drift.has_drift());
+        assert_eq!(drift.coin_total(), Some((100.0, 5.0)));
+        assert_eq!(drift.first_transaction(), Some((0, 1667260800)));
+        assert_eq!(drift.last_transaction(), None);
+        assert_eq!(address.coin_total(), 5.0);
+        assert!(!address.needs_update());
+        assert!(address.refresh().is_none());
+    }
+
+    #[test]
+    fn recompute_full_repairs_is_miner_test() {
+        let mut address = address_with_history();
+        address.set_is_miner(true);
+        address.set_needs_update(true);
+        assert_eq!(address.refresh().unwrap().is_miner(), Some((true, false)));
+        assert!(!address.is_miner());
+
+        let mut reward = TransactionAmount::new(50.0, "hashy_address".to_string(), "coinbase".to_string(), 1675296000, 0);
+        reward.set_is_coinbase(true);
+        let mut amounts = address.history().as_vec().clone();
+        amounts.push(reward);
+        address.set_transactions(amounts);
+        address.set_coin_total(55.0);
+        address.set_last_transaction(1675296000);
+        address.set_needs_update(true);
+        assert_eq!(address.refresh().unwrap().is_miner(), Some((false, true)));
+        assert!(address.is_miner());
+    }
+
+    #[test]
+    fn recompute_incremental_test() {
+        let mut address = address_with_history();
+        let mut reward = TransactionAmount::new(50.0, "hashy_address".to_string(), "coinbase".to_string(), 1675296000, 0);
+        reward.set_is_coinbase(true);
+
+        let drift = address.recompute(vec![reward], RecomputeMode::Incremental);
+
+        assert!(!drift.has_drift());
+        assert_eq!(drift.applied(), 1);
+        assert_eq!(address.coin_total(), 55.0);
+        assert_eq!(address.last_transaction(), 1675296000);
+        assert!(address.is_miner());
+        assert!(!address.needs_update());
+    }
+
+    #[test]
+    fn out_of_order_amounts_test() {
+        let mut address = Address::new("hashy_address".to_string());
+        for date in [300, 100, 200] {
+            let tx_amount = TransactionAmount::new(1.0, "hashy_address".to_string(), "transaction_hash".to_string(), date, 0);
+            address.add_transaction_amount(tx_amount, false);
+        }
+
+        let dates: Vec<i64> = address.transactions().iter().map(|a| a.date()).collect();
+        assert_eq!(dates, vec![100, 200, 300]);
+        assert_eq!(address.first_transaction(), 100);
+        assert_eq!(address.last_transaction(), 300);
+        assert_eq!(address.history().range(150, 300).len(), 2);
+    }
+
+    #[test]
+    fn address_type_test() {
+        assert_eq!(AddressType::from_hash("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"), AddressType::P2PKH);
+        assert_eq!(AddressType::from_hash("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"), AddressType::P2SH);
+        assert_eq!(AddressType::from_hash("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"), AddressType::P2WPKH);
+        assert_eq!(AddressType::from_hash("bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"), AddressType::P2WSH);
+        assert_eq!(AddressType::from_hash("bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"), AddressType::P2TR);
+        assert_eq!(AddressType::from_hash("DH5yaieqoZN36fDVciNyRueRGvGLR3mr7L"), AddressType::P2PKH);
+        assert_eq!(AddressType::from_hash("hashy_address"), AddressType::Unknown);
+    }
+
     #[test]
     fn deserialize_test() {
         let raw = r#"{
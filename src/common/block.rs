@@ -7,7 +7,8 @@ use getset::{CopyGetters, Getters, MutGetters, Setters};
 use chrono::{Utc, SecondsFormat};
 use postgres_types::{ToSql, FromSql};
 
-use crate::common::transaction::{Transaction};
+use crate::common::transaction::{Transaction, to_satoshis, SATOSHIS_PER_COIN};
+use crate::common::blockchain::BlockChainNames;
 
 #[derive(Serialize, Deserialize, Debug, Clone, NamedType, Default, Getters, CopyGetters, MutGetters, Setters, ToSql, FromSql)]
 pub struct Block {
@@ -45,10 +46,100 @@ impl Block {
             transactions: vec![]
         }
     }
+
+    // Checks the coinbase against BIP34 and the chain's subsidy schedule.
+    // Returns None when the block has no coinbase or the chain has no fixed subsidy.
+    pub fn validate_coinbase(&self, chain: &BlockChainNames) -> Option<CoinbaseValidation> {
+        let coinbase = self.transactions.iter().find(|tx| tx.is_coinbase())?;
+        let subsidy = chain.block_subsidy(self.height)?;
+
+        let bip34_height = coinbase.bip34_height();
+        let bip34_enforced = chain.bip34_activation_height()
+            .map(|activation| self.height >= activation)
+            .unwrap_or(false);
+        let bip34_valid = !bip34_enforced || bip34_height == Some(self.height);
+
+        let fees: f64 = self.transactions.iter()
+            .filter(|tx| !tx.is_coinbase())
+            .map(|tx| tx.fee())
+            .sum();
+        let claimed = coinbase.output_total();
+
+        let allowed = to_satoshis(subsidy) + to_satoshis(fees);
+        let difference = to_satoshis(claimed) - allowed;
+        let status = if difference > 0 {
+            CoinbaseStatus::OverClaimed
+        } else if difference < 0 {
+            CoinbaseStatus::UnderClaimed
+        } else {
+            CoinbaseStatus::Exact
+        };
+        let unclaimed = if status == CoinbaseStatus::UnderClaimed && chain.subsidy_is_exact(self.height) {
+            -difference as f64 / SATOSHIS_PER_COIN
+        } else {
+            0.0
+        };
+
+        Some(CoinbaseValidation {
+            block_hash: self.hash.clone(),
+            block_height: self.height,
+            bip34_height,
+            bip34_valid,
+            subsidy,
+            fees,
+            claimed,
+            unclaimed,
+            status
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CoinbaseStatus {
+    Exact,
+    UnderClaimed,
+    OverClaimed, // Invalid: miner claimed more than subsidy + fees
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct CoinbaseValidation {
+    #[getset(get = "pub")]
+    block_hash: String,
+
+    #[getset(get_copy = "pub")]
+    block_height: i64,
+
+    #[getset(get_copy = "pub")]
+    bip34_height: Option<i64>,
+
+    #[getset(get_copy = "pub")]
+    bip34_valid: bool,
+
+    #[getset(get_copy = "pub")]
+    subsidy: f64,
+
+    #[getset(get_copy = "pub")]
+    fees: f64,
+
+    #[getset(get_copy = "pub")]
+    claimed: f64,
+
+    #[getset(get_copy = "pub")]
+    unclaimed: f64, // permanently lost to under-claiming
+
+    #[getset(get_copy = "pub")]
+    status: CoinbaseStatus
+}
+
+impl CoinbaseValidation {
+    pub fn is_valid(&self) -> bool {
+        self.bip34_valid && self.status != CoinbaseStatus::OverClaimed
+    }
+}
+
 impl DeviiTrait for Block {
     fn fetch_fields() -> String {
-        format!("{{ hash, date, height, is_final, last_updated, transaction_collection {{ hash, is_coinbase, date, block_hash, block_height, last_updated, transaction_amount_collection {{ amount, address_hash, transaction_hash, date, index, vin_index, vin_hash }} }} }}")
+        format!("{{ hash, date, height, is_final, last_updated, transaction_collection {{ hash, is_coinbase, date, block_hash, block_height, last_updated, coinbase, locktime, transaction_amount_collection {{ amount, address_hash, transaction_hash, date, index, vin_index, vin_hash, block_height, is_coinbase, script_sig, witness, sequence }} }} }}")
     }
     fn insert_query(&self, param: String) -> String{
         format!("create_block (input: ${} ){{ hash }}", param)
@@ -74,8 +165,62 @@ impl DeviiTrait for Block {
 
 #[cfg(test)]
 mod tests {
-    use crate::common::block::Block;
+    use crate::common::block::{Block, CoinbaseStatus};
+    use crate::common::blockchain::BlockChainNames;
     use crate::common::transaction::{ Transaction, TransactionAmount };
+    use crate::test_fixtures::input;
+
+    fn coinbase_block(height: i64, script: &str, claimed: f64) -> Block {
+        let mut block = Block::new("blocky_hash".to_string(), 123456789, height);
+        let mut coinbase = Transaction::new_from_block("coinbase".to_string(), true, &block);
+        coinbase.set_coinbase(Some(script.to_string()));
+        coinbase.set_transaction_amounts(vec![TransactionAmount::new(claimed, "miner".to_string(), "coinbase".to_string(), 123456789, 0)]);
+
+        let mut spend = Transaction::new_from_block("spend".to_string(), false, &block);
+        spend.set_transaction_amounts(vec![input("sender", "spend", 1.0, 123456789), TransactionAmount::new(0.75, "receiver".to_string(), "spend".to_string(), 123456789, 0)]);
+
+        block.set_transactions(vec![coinbase, spend]);
+        block
+    }
+
+    #[test]
+    fn validate_coinbase_exact_test() {
+        let block = coinbase_block(227931, "035b7a03", 25.25);
+        let validation = block.validate_coinbase(&BlockChainNames::Bitcoin).unwrap();
+
+        assert_eq!(validation.bip34_height(), Some(227931));
+        assert_eq!(validation.fees(), 0.25);
+        assert_eq!(validation.status(), CoinbaseStatus::Exact);
+        assert_eq!(validation.unclaimed(), 0.0);
+        assert!(validation.is_valid());
+    }
+
+    #[test]
+    fn validate_coinbase_over_claimed_test() {
+        let block = coinbase_block(227931, "035b7a03", 26.0);
+        let validation = block.validate_coinbase(&BlockChainNames::Bitcoin).unwrap();
+
+        assert_eq!(validation.status(), CoinbaseStatus::OverClaimed);
+        assert!(!validation.is_valid());
+    }
+
+    #[test]
+    fn validate_coinbase_under_claimed_test() {
+        let block = coinbase_block(227931, "035b7a03", 25.0);
+        let validation = block.validate_coinbase(&BlockChainNames::Bitcoin).unwrap();
+
+        assert_eq!(validation.status(), CoinbaseStatus::UnderClaimed);
+        assert_eq!(validation.unclaimed(), 0.25);
+    }
+
+    #[test]
+    fn validate_coinbase_bip34_mismatch_test() {
+        let block = coinbase_block(227932, "035b7a03", 25.25);
+        let validation = block.validate_coinbase(&BlockChainNames::Bitcoin).unwrap();
+
+        assert!(!validation.bip34_valid());
+        assert!(!validation.is_valid());
+    }
 
     #[test]
     fn block_hash_test() {
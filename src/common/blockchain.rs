@@ -9,6 +9,15 @@ use serde::{Deserializer, Serializer};
 use std::error::Error;
 use std::fmt;
 
+use crate::analytics::distribution::{BalanceBand, SupplyDistribution};
+use crate::analytics::dormancy::DormancyStats;
+use crate::analytics::labels::LabelSupply;
+use crate::analytics::patterns::PatternCounts;
+use crate::analytics::price::MarketStats;
+use crate::analytics::reuse::ReuseStats;
+use crate::analytics::throughput::ThroughputStats;
+use crate::common::block::CoinbaseValidation;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum BlockChainStatType {
     Default, // Always have a time range that ends with SystemTime::now()
@@ -49,6 +58,9 @@ pub struct ChainStats {
     #[serde(default = "default_f64")]
     total_unknown_supply: f64,
 
+    #[serde(default = "default_f64")]
+    total_unclaimed_supply: f64, // coinbase rewards miners never claimed
+
     #[serde(default = "default_i64")]
     block_height: i64,
 
@@ -67,6 +79,116 @@ pub struct ChainStats {
     #[serde(default = "default_i64")]
     active_addresses: i64,
 
+    #[serde(default = "default_i64")]
+    coinjoin_count: i64,
+
+    #[serde(default = "default_i64")]
+    batch_payment_count: i64,
+
+    #[serde(default = "default_i64")]
+    consolidation_count: i64,
+
+    #[serde(default = "default_i64")]
+    simple_payment_count: i64,
+
+    #[serde(default = "default_i64")]
+    self_transfer_count: i64,
+
+    #[serde(default = "default_i64")]
+    peel_chain_count: i64,
+
+    #[serde(default = "default_f64")]
+    exchange_supply: f64,
+
+    #[serde(default = "default_f64")]
+    mixer_supply: f64,
+
+    #[serde(default = "default_f64")]
+    mining_pool_supply: f64,
+
+    #[serde(default = "default_f64")]
+    burned_supply: f64,
+
+    #[serde(default = "default_f64")]
+    sanctioned_supply: f64,
+
+    #[serde(default = "default_f64")]
+    coin_days_destroyed: f64,
+
+    #[serde(default = "default_f64")]
+    average_dormancy: f64,
+
+    #[serde(default = "default_i64")]
+    binary_cdd: i64,
+
+    #[serde(default = "default_f64")]
+    liveliness: f64,
+
+    #[serde(default = "default_f64")]
+    gini_coefficient: f64,
+
+    #[serde(default = "default_i64")]
+    nakamoto_coefficient: i64,
+
+    // Balance bands and the share held by the rich list, only set when a distribution is computed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    balance_bands: Vec<BalanceBand>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_holder_count: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_holder_share: Option<f64>,
+
+    #[serde(default = "default_i64")]
+    transaction_count: i64,
+
+    #[serde(default = "default_i64")]
+    transaction_amount_count: i64,
+
+    #[serde(default = "default_f64")]
+    transferred_volume: f64,
+
+    #[serde(default = "default_f64")]
+    adjusted_volume: f64,
+
+    #[serde(default = "default_f64")]
+    velocity: f64,
+
+    #[serde(default = "default_f64")]
+    median_transfer: f64,
+
+    #[serde(default = "default_f64")]
+    mean_transfer: f64,
+
+    #[serde(default = "default_f64")]
+    tps: f64,
+
+    #[serde(default = "default_f64")]
+    market_cap: f64,
+
+    #[serde(default = "default_f64")]
+    realized_cap: f64,
+
+    #[serde(default = "default_f64")]
+    mvrv: f64,
+
+    #[serde(default = "default_f64")]
+    nvt: f64,
+
+    // Reuse and privacy metrics are only set when computed for the window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reused_output_share: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reused_address_count: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exposed_pubkey_count: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exposed_pubkey_supply: Option<f64>,
+
     #[serde(default = "default_i64")]
     last_updated: i64,
 
@@ -176,12 +298,49 @@ impl ChainStats {
             total_coin_issuance: 0.0,
             total_active_coins: 0.0,
             total_unknown_supply: 0.0,
+            total_unclaimed_supply: 0.0,
             block_height: 0,
             block_range_start: 0,
             block_range_end: 0,
             date_range_start: 0,
             date_range_end: 0,
             active_addresses: 0,
+            coinjoin_count: 0,
+            batch_payment_count: 0,
+            consolidation_count: 0,
+            simple_payment_count: 0,
+            self_transfer_count: 0,
+            peel_chain_count: 0,
+            exchange_supply: 0.0,
+            mixer_supply: 0.0,
+            mining_pool_supply: 0.0,
+            burned_supply: 0.0,
+            sanctioned_supply: 0.0,
+            coin_days_destroyed: 0.0,
+            average_dormancy: 0.0,
+            binary_cdd: 0,
+            liveliness: 0.0,
+            gini_coefficient: 0.0,
+            nakamoto_coefficient: 0,
+            balance_bands: vec![],
+            top_holder_count: None,
+            top_holder_share: None,
+            transaction_count: 0,
+            transaction_amount_count: 0,
+            transferred_volume: 0.0,
+            adjusted_volume: 0.0,
+            velocity: 0.0,
+            median_transfer: 0.0,
+            mean_transfer: 0.0,
+            tps: 0.0,
+            market_cap: 0.0,
+            realized_cap: 0.0,
+            mvrv: 0.0,
+            nvt: 0.0,
+            reused_output_share: None,
+            reused_address_count: None,
+            exposed_pubkey_count: None,
+            exposed_pubkey_supply: None,
             last_updated: 0,
             stat_type: BlockChainStatType::default(),
         }
@@ -213,6 +372,114 @@ impl ChainStats {
     pub fn block_height(&self) -> i64 {
         self.block_height
     }
+    pub fn coinjoin_count(&self) -> i64 {
+        self.coinjoin_count
+    }
+    pub fn batch_payment_count(&self) -> i64 {
+        self.batch_payment_count
+    }
+    pub fn consolidation_count(&self) -> i64 {
+        self.consolidation_count
+    }
+    pub fn simple_payment_count(&self) -> i64 {
+        self.simple_payment_count
+    }
+    pub fn self_transfer_count(&self) -> i64 {
+        self.self_transfer_count
+    }
+    pub fn peel_chain_count(&self) -> i64 {
+        self.peel_chain_count
+    }
+    pub fn exchange_supply(&self) -> f64 {
+        self.exchange_supply
+    }
+    pub fn mixer_supply(&self) -> f64 {
+        self.mixer_supply
+    }
+    pub fn mining_pool_supply(&self) -> f64 {
+        self.mining_pool_supply
+    }
+    pub fn burned_supply(&self) -> f64 {
+        self.burned_supply
+    }
+    pub fn sanctioned_supply(&self) -> f64 {
+        self.sanctioned_supply
+    }
+    pub fn coin_days_destroyed(&self) -> f64 {
+        self.coin_days_destroyed
+    }
+    pub fn average_dormancy(&self) -> f64 {
+        self.average_dormancy
+    }
+    pub fn binary_cdd(&self) -> i64 {
+        self.binary_cdd
+    }
+    pub fn liveliness(&self) -> f64 {
+        self.liveliness
+    }
+    pub fn gini_coefficient(&self) -> f64 {
+        self.gini_coefficient
+    }
+    pub fn nakamoto_coefficient(&self) -> i64 {
+        self.nakamoto_coefficient
+    }
+    pub fn balance_bands(&self) -> &Vec<BalanceBand> {
+        &self.balance_bands
+    }
+    pub fn top_holder_count(&self) -> Option<i64> {
+        self.top_holder_count
+    }
+    pub fn top_holder_share(&self) -> Option<f64> {
+        self.top_holder_share
+    }
+    pub fn transaction_count(&self) -> i64 {
+        self.transaction_count
+    }
+    pub fn transaction_amount_count(&self) -> i64 {
+        self.transaction_amount_count
+    }
+    pub fn transferred_volume(&self) -> f64 {
+        self.transferred_volume
+    }
+    pub fn adjusted_volume(&self) -> f64 {
+        self.adjusted_volume
+    }
+    pub fn velocity(&self) -> f64 {
+        self.velocity
+    }
+    pub fn median_transfer(&self) -> f64 {
+        self.median_transfer
+    }
+    pub fn mean_transfer(&self) -> f64 {
+        self.mean_transfer
+    }
+    pub fn tps(&self) -> f64 {
+        self.tps
+    }
+    pub fn market_cap(&self) -> f64 {
+        self.market_cap
+    }
+    pub fn realized_cap(&self) -> f64 {
+        self.realized_cap
+    }
+    pub fn mvrv(&self) -> f64 {
+        self.mvrv
+    }
+    pub fn nvt(&self) -> f64 {
+        self.nvt
+    }
+    pub fn reused_output_share(&self) -> Option<f64> {
+        self.reused_output_share
+    }
+    pub fn reused_address_count(&self) -> Option<i64> {
+        self.reused_address_count
+    }
+    pub fn exposed_pubkey_count(&self) -> Option<i64> {
+        self.exposed_pubkey_count
+    }
+    pub fn exposed_pubkey_supply(&self) -> Option<f64> {
+        self.exposed_pubkey_supply
+    }
     pub fn total_coin_issuance(&self) -> f64 {
         self.total_coin_issuance
     }
@@ -225,6 +492,9 @@ impl ChainStats {
     pub fn total_unknown_supply(&self) -> f64 {
         self.total_unknown_supply
     }
+    pub fn total_unclaimed_supply(&self) -> f64 {
+        self.total_unclaimed_supply
+    }
     pub fn short_description(&self) -> String {
         self.short_description.clone()
     }
@@ -253,6 +523,63 @@ impl ChainStats {
         self.block_height = height;
         self
     }
+    pub fn update_pattern_counts(&mut self, counts: &PatternCounts) -> &mut Self {
+        self.coinjoin_count = counts.coinjoin();
+        self.batch_payment_count = counts.batch_payment();
+        self.consolidation_count = counts.consolidation();
+        self.simple_payment_count = counts.simple_payment();
+        self.self_transfer_count = counts.self_transfer();
+        self.peel_chain_count = counts.peel_chain();
+        self
+    }
+    pub fn update_label_supply(&mut self, supply: &LabelSupply) -> &mut Self {
+        self.exchange_supply = supply.exchange();
+        self.mixer_supply = supply.mixer();
+        self.mining_pool_supply = supply.mining_pool();
+        self.burned_supply = supply.burn();
+        self.sanctioned_supply = supply.sanctioned();
+        self
+    }
+    pub fn update_dormancy(&mut self, stats: &DormancyStats) -> &mut Self {
+        self.coin_days_destroyed = stats.coin_days_destroyed();
+        self.average_dormancy = stats.average_dormancy();
+        self.binary_cdd = stats.binary_cdd();
+        self.liveliness = stats.liveliness();
+        self
+    }
+    pub fn update_distribution(&mut self, distribution: &SupplyDistribution) -> &mut Self {
+        self.gini_coefficient = distribution.gini_coefficient();
+        self.nakamoto_coefficient = distribution.nakamoto_coefficient();
+        self.balance_bands = distribution.bands().clone();
+        self.top_holder_count = Some(distribution.rich_list().len() as i64);
+        self.top_holder_share = Some(distribution.top_share());
+        self
+    }
+    pub fn update_throughput(&mut self, stats: &ThroughputStats) -> &mut Self {
+        self.transaction_count = stats.transaction_count();
+        self.transaction_amount_count = stats.transaction_amount_count();
+        self.transferred_volume = stats.transferred_volume();
+        self.adjusted_volume = stats.adjusted_volume();
+        self.velocity = stats.velocity();
+        self.median_transfer = stats.median_transfer();
+        self.mean_transfer = stats.mean_transfer();
+        self.tps = stats.tps();
+        self
+    }
+    pub fn update_market(&mut self, stats: &MarketStats) -> &mut Self {
+        self.market_cap = stats.market_cap();
+        self.realized_cap = stats.realized_cap();
+        self.mvrv = stats.mvrv();
+        self.nvt = stats.nvt();
+        self
+    }
+    pub fn update_reuse(&mut self, stats: &ReuseStats) -> &mut Self {
+        self.reused_output_share = Some(stats.reused_output_share());
+        self.reused_address_count = Some(stats.reused_address_count());
+        self.exposed_pubkey_count = Some(stats.exposed_pubkey_count());
+        self.exposed_pubkey_supply = Some(stats.exposed_pubkey_supply());
+        self
+    }
     pub fn update_total_coin_issuance_by_block(&mut self, mut block_height: i64) -> () {
         let mut bitcoin_reward: f64 = 50.0;
         let mut total_mined: f64 = 0.0;
@@ -276,11 +603,18 @@ impl ChainStats {
     pub fn update_total_unknown_supply(&mut self, amount: f64) -> () {
         self.total_unknown_supply = amount;
     }
+    pub fn update_total_unclaimed_supply(&mut self, amount: f64) {
+        self.total_unclaimed_supply = amount;
+    }
+    pub fn record_coinbase(&mut self, validation: &CoinbaseValidation) -> &mut Self {
+        self.total_unclaimed_supply += validation.unclaimed();
+        self
+    }
 }
 
 impl DeviiTrait for ChainStats {
     fn fetch_fields() -> String {
-        format!("{{ id, blockchain_name, short_description, time_offset, total_coin_issuance, total_active_coins, total_unknown_supply, block_height, block_range_start, block_range_end, date_range_start, date_range_end, active_addresses, last_updated, stat_type}}")
+        format!("{{ id, blockchain_name, short_description, time_offset, total_coin_issuance, total_active_coins, total_unknown_supply, total_unclaimed_supply, block_height, block_range_start, block_range_end, date_range_start, date_range_end, active_addresses, coinjoin_count, batch_payment_count, consolidation_count, simple_payment_count, self_transfer_count, peel_chain_count, exchange_supply, mixer_supply, mining_pool_supply, burned_supply, sanctioned_supply, coin_days_destroyed, average_dormancy, binary_cdd, liveliness, gini_coefficient, nakamoto_coefficient, balance_bands {{ label, min, max, address_count, supply }}, top_holder_count, top_holder_share, transaction_count, transaction_amount_count, transferred_volume, adjusted_volume, velocity, median_transfer, mean_transfer, tps, market_cap, realized_cap, mvrv, nvt, reused_output_share, reused_address_count, exposed_pubkey_count, exposed_pubkey_supply, last_updated, stat_type}}")
     }
     fn insert_query(&self, param: String) -> String {
         format!("create_chain_stats (input: ${} ){{ id }}", param)
@@ -296,7 +630,7 @@ impl DeviiTrait for ChainStats {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BlockChainNames {
     Bitcoin,
     BitcoinCash,
@@ -307,6 +641,79 @@ pub enum BlockChainNames {
     EthereumClassic,
 }
 
+impl BlockChainNames {
+    pub fn all() -> [BlockChainNames; 7] {
+        [
+            BlockChainNames::Bitcoin,
+            BlockChainNames::BitcoinCash,
+            BlockChainNames::Dogecoin,
+            BlockChainNames::Litecoin,
+            BlockChainNames::Dash,
+            BlockChainNames::Ethereum,
+            BlockChainNames::EthereumClassic,
+        ]
+    }
+
+    // Maximum coinbase subsidy (excluding fees) for a block, None for chains
+    // where the subsidy can't be derived from the height alone
+    pub fn block_subsidy(&self, height: i64) -> Option<f64> {
+        const COIN: i64 = 100_000_000;
+        let halvings = |interval: i64| -> f64 {
+            let halvings = height / interval;
+            if halvings >= 64 {
+                return 0.0
+            }
+            ((50 * COIN) >> halvings) as f64 / COIN as f64
+        };
+        match self {
+            BlockChainNames::Bitcoin | BlockChainNames::BitcoinCash => Some(halvings(210_000)),
+            BlockChainNames::Litecoin => Some(halvings(840_000)),
+            BlockChainNames::Dogecoin => Some(match height {
+                h if h < 100_000 => 1_000_000.0,
+                h if h < 145_000 => 500_000.0,
+                h if h < 200_000 => 250_000.0,
+                h if h < 300_000 => 125_000.0,
+                h if h < 400_000 => 62_500.0,
+                h if h < 500_000 => 31_250.0,
+                h if h < 600_000 => 15_625.0,
+                _ => 10_000.0,
+            }),
+            // Dash rewards depend on difficulty, Ethereum has no coinbase output
+            BlockChainNames::Dash | BlockChainNames::Ethereum | BlockChainNames::EthereumClassic => None,
+        }
+    }
+
+    // Early Dogecoin rewards were random up to the subsidy, so an under-claim isn't unclaimed supply
+    pub fn subsidy_is_exact(&self, height: i64) -> bool {
+        !(self == &BlockChainNames::Dogecoin && height < 145_000)
+    }
+
+    // Number of blocks a coinbase output must wait before it can be spent
+    pub fn coinbase_maturity(&self, height: i64) -> i64 {
+        match self {
+            BlockChainNames::Dogecoin if height < 145_000 => 30,
+            BlockChainNames::Dogecoin => 240,
+            BlockChainNames::Ethereum | BlockChainNames::EthereumClassic => 0,
+            _ => 100,
+        }
+    }
+
+    // A coinbase is spendable once the next block would be `maturity` blocks deep
+    pub fn coinbase_is_mature(&self, block_height: i64, tip_height: i64) -> bool {
+        tip_height + 1 - block_height >= self.coinbase_maturity(block_height)
+    }
+
+    pub fn bip34_activation_height(&self) -> Option<i64> {
+        match self {
+            BlockChainNames::Bitcoin | BlockChainNames::BitcoinCash => Some(227_931),
+            BlockChainNames::Litecoin => Some(710_000),
+            BlockChainNames::Dogecoin => Some(1_034_383),
+            BlockChainNames::Dash => Some(951),
+            BlockChainNames::Ethereum | BlockChainNames::EthereumClassic => None,
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for BlockChainNames {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -474,6 +881,74 @@ mod test {
         assert_eq!(new_name, BlockChainNames::BitcoinCash);
     }
     #[test]
+    fn test_block_subsidy() {
+        assert_eq!(BlockChainNames::Bitcoin.block_subsidy(0), Some(50.0));
+        assert_eq!(BlockChainNames::Bitcoin.block_subsidy(210_000), Some(25.0));
+        assert_eq!(BlockChainNames::Bitcoin.block_subsidy(840_000), Some(3.125));
+        assert_eq!(BlockChainNames::Bitcoin.block_subsidy(64 * 210_000), Some(0.0));
+        assert_eq!(BlockChainNames::Litecoin.block_subsidy(840_000), Some(25.0));
+        assert_eq!(BlockChainNames::Dogecoin.block_subsidy(4_000_000), Some(10_000.0));
+        assert_eq!(BlockChainNames::Dash.block_subsidy(1), None);
+    }
+    #[test]
+    fn test_coinbase_maturity() {
+        assert_eq!(BlockChainNames::Bitcoin.coinbase_maturity(700_000), 100);
+        assert_eq!(BlockChainNames::Dogecoin.coinbase_maturity(100_000), 30);
+        assert_eq!(BlockChainNames::Dogecoin.coinbase_maturity(145_000), 240);
+
+        assert!(!BlockChainNames::Bitcoin.coinbase_is_mature(100, 198));
+        assert!(BlockChainNames::Bitcoin.coinbase_is_mature(100, 199));
+    }
+    #[test]
+    fn test_record_coinbase() {
+        use crate::common::block::Block;
+        use crate::common::transaction::{Transaction, TransactionAmount};
+
+        let mut block = Block::new("blocky_hash".to_string(), 123456789, 210_000);
+        let mut coinbase = Transaction::new_from_block("coinbase".to_string(), true, &block);
+        coinbase.set_transaction_amounts(vec![TransactionAmount::new(20.0, "miner".to_string(), "coinbase".to_string(), 123456789, 0)]);
+        block.set_transactions(vec![coinbase]);
+
+        let validation = block.validate_coinbase(&BlockChainNames::Bitcoin).unwrap();
+        let mut stats = ChainStats::new(BlockChainNames::Bitcoin, "bitcoin_30_days".to_string(), 2592000);
+        stats.record_coinbase(&validation);
+
+        assert_eq!(stats.total_unclaimed_supply(), 5.0);
+    }
+    #[test]
+    fn test_update_pattern_counts() {
+        use crate::analytics::patterns::{PatternCounts, TransactionPattern};
+
+        let mut counts = PatternCounts::new();
+        counts.add(TransactionPattern::CoinJoin).add(TransactionPattern::CoinJoin).add(TransactionPattern::PeelChain);
+        let mut stats = ChainStats::new(BlockChainNames::Bitcoin, "bitcoin_30_days".to_string(), 2592000);
+        stats.update_pattern_counts(&counts);
+
+        assert_eq!(stats.coinjoin_count(), 2);
+        assert_eq!(stats.peel_chain_count(), 1);
+        assert_eq!(stats.batch_payment_count(), 0);
+    }
+    #[test]
+    fn test_update_distribution() {
+        use crate::analytics::distribution::{BalanceBands, SupplyDistribution};
+
+        let balances = [("a", 60.0), ("b", 30.0), ("c", 10.0)];
+        let distribution = SupplyDistribution::from_balances(balances.into_iter(), &BalanceBands::new(vec![20.0]).unwrap(), 2);
+        let mut stats = ChainStats::new(BlockChainNames::Bitcoin, "bitcoin_30_days".to_string(), 2592000);
+        assert!(stats.top_holder_share().is_none());
+        stats.update_distribution(&distribution);
+
+        assert_eq!(stats.nakamoto_coefficient(), 1);
+        assert_eq!(stats.balance_bands().len(), 2);
+        assert_eq!(stats.balance_bands()[1].address_count(), 2);
+        assert_eq!(stats.balance_bands()[1].supply(), 90.0);
+        assert_eq!(stats.top_holder_count(), Some(2));
+        assert_eq!(stats.top_holder_share(), Some(0.9));
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["balance_bands"][0]["label"], "<20");
+    }
+    #[test]
     fn test_serde_blockchain_names_eth() {
         let name = BlockChainNames::EthereumClassic;
         let string_name = serde_json::to_string(&name).unwrap();
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::transaction::TransactionAmount;
+
+// Transaction amounts kept in ascending date order. Appends in date order are
+// O(1); out of order amounts are placed with a binary search.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(from = "Vec<TransactionAmount>", into = "Vec<TransactionAmount>")]
+pub struct TransactionHistory {
+    amounts: Vec<TransactionAmount>
+}
+
+impl TransactionHistory {
+    pub fn new() -> Self {
+        TransactionHistory { amounts: Vec::new() }
+    }
+
+    pub fn insert(&mut self, tx_amount: TransactionAmount) -> &mut Self {
+        let in_order = self.amounts.last()
+            .map(|last| last.date() <= tx_amount.date())
+            .unwrap_or(true);
+
+        if in_order {
+            self.amounts.push(tx_amount);
+        } else {
+            // After any amounts sharing the same date so insertion order is kept
+            let position = self.amounts.partition_point(|a| a.date() <= tx_amount.date());
+            self.amounts.insert(position, tx_amount);
+        }
+        self
+    }
+
+    pub fn first_date(&self) -> Option<i64> {
+        self.amounts.first().map(|a| a.date())
+    }
+
+    pub fn last_date(&self) -> Option<i64> {
+        self.amounts.last().map(|a| a.date())
+    }
+
+    // Amounts dated within [start, end]
+    pub fn range(&self, start: i64, end: i64) -> &[TransactionAmount] {
+        let from = self.amounts.partition_point(|a| a.date() < start);
+        let to = self.amounts.partition_point(|a| a.date() <= end);
+        if from >= to {
+            return &[]
+        }
+        &self.amounts[from..to]
+    }
+
+    // Amounts dated at or before `time`
+    pub fn until(&self, time: i64) -> &[TransactionAmount] {
+        let to = self.amounts.partition_point(|a| a.date() <= time);
+        &self.amounts[..to]
+    }
+
+    pub fn page(&self, start: i64, end: i64, page: usize, page_size: usize) -> &[TransactionAmount] {
+        self.range(start, end)
+            .chunks(page_size.max(1))
+            .nth(page)
+            .unwrap_or(&[])
+    }
+
+    pub fn pages(&self, start: i64, end: i64, page_size: usize) -> std::slice::Chunks<'_, TransactionAmount> {
+        self.range(start, end).chunks(page_size.max(1))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, TransactionAmount> {
+        self.amounts.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.amounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amounts.is_empty()
+    }
+
+    pub fn as_vec(&self) -> &Vec<TransactionAmount> {
+        &self.amounts
+    }
+}
+
+impl From<Vec<TransactionAmount>> for TransactionHistory {
+    fn from(mut amounts: Vec<TransactionAmount>) -> Self {
+        amounts.sort_by_key(|a| a.date());
+        TransactionHistory { amounts }
+    }
+}
+
+impl From<TransactionHistory> for Vec<TransactionAmount> {
+    fn from(history: TransactionHistory) -> Self {
+        history.amounts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::history::TransactionHistory;
+    use crate::common::transaction::TransactionAmount;
+
+    fn amount(date: i64, index: i32) -> TransactionAmount {
+        TransactionAmount::new(1.0, "address".to_string(), "transaction_hash".to_string(), date, index)
+    }
+
+    #[test]
+    fn insert_keeps_date_order_test() {
+        let mut history = TransactionHistory::new();
+        history.insert(amount(30, 0)).insert(amount(10, 1)).insert(amount(20, 2)).insert(amount(20, 3));
+
+        let indexes: Vec<i32> = history.iter().map(|a| a.index()).collect();
+        assert_eq!(indexes, vec![1, 2, 3, 0]);
+        assert_eq!(history.first_date(), Some(10));
+        assert_eq!(history.last_date(), Some(30));
+    }
+
+    #[test]
+    fn range_and_pages_test() {
+        let history = TransactionHistory::from((0..10).map(|i| amount(i * 10, i as i32)).collect::<Vec<_>>());
+
+        assert_eq!(history.range(15, 45).len(), 3);
+        assert_eq!(history.range(50, 40).len(), 0);
+        assert_eq!(history.until(20).len(), 3);
+        assert_eq!(history.page(0, 90, 1, 4)[0].index(), 4);
+        assert_eq!(history.page(0, 90, 5, 4).len(), 0);
+        assert_eq!(history.pages(0, 90, 4).count(), 3);
+    }
+
+    #[test]
+    fn serde_as_sorted_vec_test() {
+        let history = TransactionHistory::from(vec![amount(20, 0), amount(10, 1)]);
+        let raw = serde_json::to_string(&history).unwrap();
+        let parsed: TransactionHistory = serde_json::from_str(&raw).unwrap();
+
+        assert!(raw.starts_with("[{"));
+        assert_eq!(parsed.first_date(), Some(10));
+        assert_eq!(parsed.len(), 2);
+    }
+}
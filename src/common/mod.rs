@@ -1,4 +1,7 @@
 pub mod address;
 pub mod transaction;
 pub mod blockchain;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod period;
+pub mod history;
+pub mod wallet;
\ No newline at end of file
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+
+const HOUR: i64 = 3600;
+const DAY: i64 = 86400;
+const WEEK: i64 = 604800;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Period {
+    Hour,
+    Day,
+    Week, // Weeks start on Monday
+    Month,
+    Year,
+}
+
+impl Period {
+    // Unix time of the start of the period containing `time`
+    pub fn start_of(&self, time: i64) -> i64 {
+        match self {
+            Period::Hour => time - time.rem_euclid(HOUR),
+            Period::Day => time - time.rem_euclid(DAY),
+            // 1970-01-01 was a Thursday
+            Period::Week => time - (time + 3 * DAY).rem_euclid(WEEK),
+            Period::Month => {
+                let date = utc_date(time);
+                timestamp(date.year(), date.month())
+            },
+            Period::Year => timestamp(utc_date(time).year(), 1),
+        }
+    }
+
+    // Unix time of the start of the period following the one containing `time`
+    pub fn next(&self, time: i64) -> i64 {
+        let start = self.start_of(time);
+        match self {
+            Period::Hour => start + HOUR,
+            Period::Day => start + DAY,
+            Period::Week => start + WEEK,
+            Period::Month => {
+                let date = utc_date(start);
+                if date.month() == 12 {
+                    timestamp(date.year() + 1, 1)
+                } else {
+                    timestamp(date.year(), date.month() + 1)
+                }
+            },
+            Period::Year => timestamp(utc_date(start).year() + 1, 1),
+        }
+    }
+
+    // Start times of every period overlapping [start, end]
+    pub fn range(&self, start: i64, end: i64) -> Vec<i64> {
+        let mut periods = vec![];
+        let mut current = self.start_of(start);
+        while current <= end {
+            periods.push(current);
+            current = self.next(current);
+        }
+        periods
+    }
+
+    pub fn label(&self, time: i64) -> String {
+        let date = utc_date(time);
+        match self {
+            Period::Hour => format!("{}T{:02}", date, (time.rem_euclid(DAY)) / HOUR),
+            Period::Day | Period::Week => date.to_string(),
+            Period::Month => format!("{}-{:02}", date.year(), date.month()),
+            Period::Year => date.year().to_string(),
+        }
+    }
+}
+
+fn utc_date(time: i64) -> NaiveDate {
+    Utc.timestamp_opt(time, 0).unwrap().naive_utc().date()
+}
+
+fn timestamp(year: i32, month: u32) -> i64 {
+    let date = NaiveDate::from_ymd_opt(year, month, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    Utc.from_utc_datetime(&date).timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::period::Period;
+
+    #[test]
+    fn start_of_test() {
+        // 2022-11-05T10:26:52Z (Saturday)
+        let time = 1667644012;
+        assert_eq!(Period::Hour.start_of(time), 1667642400);
+        assert_eq!(Period::Day.start_of(time), 1667606400);
+        assert_eq!(Period::Week.start_of(time), 1667174400);
+        assert_eq!(Period::Month.start_of(time), 1667260800);
+        assert_eq!(Period::Year.start_of(time), 1640995200);
+    }
+
+    #[test]
+    fn next_month_rolls_year_test() {
+        // 2022-12-15
+        assert_eq!(Period::Month.next(1671062400), 1672531200);
+    }
+
+    #[test]
+    fn range_test() {
+        let months = Period::Month.range(1667644012, 1672531200);
+        assert_eq!(months.len(), 3);
+        assert_eq!(Period::Month.label(months[0]), "2022-11".to_string());
+        assert_eq!(Period::Month.label(months[2]), "2023-01".to_string());
+    }
+}
@@ -10,6 +10,7 @@ use devii::devii::DeviiTrait;
 use getset::{CopyGetters, Getters, MutGetters, Setters};
 use postgres_types::{ToSql, FromSql};
 
+use crate::common::address::AddressType;
 use crate::common::block::Block;
 
 
@@ -34,6 +35,16 @@ pub struct Transaction {
     #[getset(get = "pub")]
     last_updated: String,
 
+    // Hex encoded scriptSig of the coinbase input (only set on coinbase transactions)
+    #[getset(get = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    coinbase: Option<String>,
+
+    // nLockTime, see absolute_timelock
+    #[getset(get_copy = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locktime: Option<i64>,
+
     #[getset(get = "pub", get_mut = "pub", set = "pub")]
     #[serde(alias = "transaction_amount_collection")]
     #[serde(rename(serialize = "transaction_amount_collection"))]
@@ -50,6 +61,8 @@ impl Transaction {
             block_hash,
             block_height,
             last_updated: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            coinbase: None,
+            locktime: None,
             transaction_amounts: vec![]
         }
     }
@@ -61,14 +74,100 @@ impl Transaction {
             block_hash: block.hash().clone(),
             block_height: block.height(),
             last_updated: Utc::now().to_string(),
+            coinbase: None,
+            locktime: None,
             transaction_amounts: vec![]
         }
     }
+
+    // BIP34: the first push of the coinbase scriptSig is the block height
+    pub fn bip34_height(&self) -> Option<i64> {
+        if !self.is_coinbase {
+            return None
+        }
+        let script = decode_hex(self.coinbase.as_ref()?)?;
+        let opcode = *script.first()?;
+        match opcode {
+            0x00 => Some(0),
+            0x51..=0x60 => Some((opcode - 0x50) as i64),
+            0x01..=0x08 => {
+                let len = opcode as usize;
+                let bytes = script.get(1..1 + len)?;
+                let mut height: i64 = 0;
+                for (i, byte) in bytes.iter().enumerate() {
+                    height |= (*byte as i64) << (8 * i);
+                }
+                // CScriptNum stores the sign in the high bit of the last byte
+                if bytes[len - 1] & 0x80 != 0 {
+                    height &= !(0x80 << (8 * (len - 1)));
+                    height = -height;
+                }
+                Some(height)
+            },
+            _ => None
+        }
+    }
+
+    pub fn input_total(&self) -> f64 {
+        self.transaction_amounts.iter()
+            .filter(|a| a.is_input())
+            .map(|a| a.amount().abs())
+            .sum()
+    }
+
+    pub fn output_total(&self) -> f64 {
+        self.transaction_amounts.iter()
+            .filter(|a| a.is_output())
+            .map(|a| a.amount())
+            .sum()
+    }
+
+    // Coinbase transactions don't pay fees, they collect them
+    pub fn fee(&self) -> f64 {
+        if self.is_coinbase {
+            return 0.0
+        }
+        self.input_total() - self.output_total()
+    }
+
+    pub fn is_segwit(&self) -> bool {
+        self.transaction_amounts.iter().any(|a| a.is_segwit())
+    }
+
+    // BIP125: opted in when any input's sequence is below 0xfffffffe
+    pub fn signals_rbf(&self) -> bool {
+        self.transaction_amounts.iter().any(|a| a.signals_rbf())
+    }
+
+    // nLockTime is ignored when it's zero or every input's sequence is final
+    pub fn absolute_timelock(&self) -> Option<Timelock> {
+        let locktime = self.locktime.filter(|l| *l > 0)?;
+        let sequences: Vec<i64> = self.transaction_amounts.iter().filter_map(|a| a.sequence()).collect();
+        if !sequences.is_empty() && sequences.iter().all(|s| *s == SEQUENCE_FINAL) {
+            return None
+        }
+        if locktime < LOCKTIME_THRESHOLD {
+            Some(Timelock::Height(locktime))
+        } else {
+            Some(Timelock::Time(locktime))
+        }
+    }
+}
+
+const SEQUENCE_FINAL: i64 = 0xffff_ffff;
+const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+
+// Absolute locks are a block height or a unix timestamp, relative locks (BIP68) a number
+// of blocks or seconds since the spent output confirmed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timelock {
+    Height(i64),
+    Time(i64),
 }
 
 impl DeviiTrait for Transaction {
     fn fetch_fields() -> String {
-        format!("{{ hash, is_coinbase, date, block_hash, block_height, last_updated, transaction_amount_collection {{ amount, address_hash, transaction_hash, date, index, last_updated }} }}")
+        format!("{{ hash, is_coinbase, date, block_hash, block_height, last_updated, coinbase, locktime, transaction_amount_collection {{ amount, address_hash, transaction_hash, date, index, last_updated, block_height, is_coinbase, script_sig, witness, sequence }} }}")
     }
     fn insert_query(&self, param: String) -> String{
         format!("create_transaction (input: ${} ){{ hash }}", param)
@@ -116,7 +215,28 @@ pub struct TransactionAmount {
     vin_index: i32, 
 
     #[getset(get = "pub", set = "pub")]
-    vin_hash: Option<String>
+    vin_hash: Option<String>,
+
+    #[getset(get_copy = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    block_height: Option<i64>,
+
+    #[getset(get_copy = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    is_coinbase: bool,
+
+    // Spend side data, scripts and witness items are hex encoded
+    #[getset(get = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    script_sig: Option<String>,
+
+    #[getset(get = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "deserialize_null_as_empty")]
+    witness: Vec<String>,
+
+    #[getset(get_copy = "pub", set = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<i64>
 }
 
 impl TransactionAmount {
@@ -128,14 +248,104 @@ impl TransactionAmount {
             date,
             index,
             vin_index: -1,
-            vin_hash: None
+            vin_hash: None,
+            block_height: None,
+            is_coinbase: false,
+            script_sig: None,
+            witness: vec![],
+            sequence: None
+        }
+    }
+
+    // Spends reference a previous output through vin_hash / vin_index
+    pub fn is_input(&self) -> bool {
+        self.vin_hash.is_some() || self.index < 0
+    }
+
+    pub fn is_output(&self) -> bool {
+        !self.is_input()
+    }
+
+    pub fn is_segwit(&self) -> bool {
+        !self.witness.is_empty()
+    }
+
+    pub fn signals_rbf(&self) -> bool {
+        self.sequence.is_some_and(|s| s < SEQUENCE_FINAL - 1)
+    }
+
+    // BIP68 lock from the sequence number. Only enforced for version 2 transactions,
+    // which isn't recorded, so treat it as what the spender asked for.
+    pub fn relative_timelock(&self) -> Option<Timelock> {
+        let sequence = self.sequence?;
+        if sequence & (1 << 31) != 0 || sequence & 0xffff == 0 {
+            return None
+        }
+        if sequence & (1 << 22) != 0 {
+            Some(Timelock::Time((sequence & 0xffff) * 512))
+        } else {
+            Some(Timelock::Height(sequence & 0xffff))
         }
     }
+
+    // Witness items without the BIP341 annex, empty if any item isn't valid hex
+    fn witness_stack(&self) -> Vec<Vec<u8>> {
+        let mut stack: Vec<Vec<u8>> = self.witness.iter().map(|item| decode_hex(item)).collect::<Option<_>>().unwrap_or_default();
+        if stack.len() >= 2 && stack.last().and_then(|item| item.first()) == Some(&0x50) {
+            stack.pop();
+        }
+        stack
+    }
+
+    // Taproot spends have no scriptSig, and the address (when it can be classified) is P2TR
+    fn may_be_taproot(&self) -> bool {
+        self.script_sig.as_ref().is_none_or(|s| s.is_empty())
+            && matches!(AddressType::from_hash(&self.address_hash), AddressType::P2TR | AddressType::Unknown)
+    }
+
+    // A single Schnorr signature, 64 bytes or 65 with a sighash type
+    pub fn is_taproot_keypath(&self) -> bool {
+        let stack = self.witness_stack();
+        self.may_be_taproot() && stack.len() == 1 && matches!(stack[0].len(), 64 | 65)
+    }
+
+    // Script inputs, the leaf script and a control block of 33 + 32m bytes starting with
+    // the tapscript leaf version (0xc0, the low bit is the output key parity)
+    pub fn is_taproot_scriptpath(&self) -> bool {
+        let stack = self.witness_stack();
+        let control_block = match stack.last() {
+            Some(control_block) if stack.len() >= 2 => control_block,
+            _ => return false,
+        };
+        let len = control_block.len();
+        self.may_be_taproot() && control_block[0] & 0xfe == 0xc0 && len >= 33 && (len - 33) % 32 == 0 && len <= 33 + 32 * 128
+    }
+}
+
+pub(crate) const SATOSHIS_PER_COIN: f64 = 100_000_000.0;
+
+// Rounds to whole satoshis so float amounts can be compared exactly
+pub(crate) fn to_satoshis(amount: f64) -> i64 {
+    (amount * SATOSHIS_PER_COIN).round() as i64
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 impl DeviiTrait for TransactionAmount {
     fn fetch_fields() -> String {
-        format!("{{ amount, address_hash, transaction_hash, date, index, vin_index, vin_hash }}")
+        format!("{{ amount, address_hash, transaction_hash, date, index, vin_index, vin_hash, block_height, is_coinbase, script_sig, witness, sequence }}")
     }
     fn insert_query(&self, param: String) -> String{
         format!("create_transaction_amount (input: ${} ){{ transaction_hash, index, vin_index }}", param)
@@ -178,6 +388,13 @@ pub fn deserialize_i32_or_string<'de, D>(deserializer: D) -> Result<i32, D::Erro
     }
 }
 
+// Outputs come back from the API with a null witness
+fn deserialize_null_as_empty<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    where D: Deserializer<'de>
+{
+    Ok(Option::<Vec<String>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 impl Ord for TransactionAmount {
     fn cmp(&self, other: &Self) -> Ordering {
         self.date.cmp(&other.date)
@@ -200,8 +417,9 @@ impl Eq for TransactionAmount {}
 #[cfg(test)]
 mod tests {
     use devii::devii::DeviiTrait;
-    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::common::transaction::{Timelock, Transaction, TransactionAmount};
     use crate::common::block::Block;
+    use crate::test_fixtures::input;
 
     #[test]
     fn transaction_get_date_test() {
@@ -300,6 +518,128 @@ mod tests {
         assert_eq!(1, transaction.transaction_amounts().len());
     }
 
+    #[test]
+    fn bip34_height_test() {
+        let mut transaction = Transaction::new("hashy".to_string(), true, 123456789, "hello_world".to_string(), 227931);
+        transaction.set_coinbase(Some("035b7a03062f503253482f".to_string()));
+
+        assert_eq!(transaction.bip34_height(), Some(227931));
+
+        transaction.set_coinbase(Some("51".to_string()));
+        assert_eq!(transaction.bip34_height(), Some(1));
+
+        transaction.set_coinbase(None);
+        assert_eq!(transaction.bip34_height(), None);
+    }
+
+    #[test]
+    fn bip34_height_not_coinbase_test() {
+        let mut transaction = Transaction::new("hashy".to_string(), false, 123456789, "hello_world".to_string(), 227931);
+        transaction.set_coinbase(Some("035b7a03".to_string()));
+
+        assert_eq!(transaction.bip34_height(), None);
+    }
+
+    #[test]
+    fn transaction_fee_test() {
+        let mut transaction = Transaction::new("hashy".to_string(), false, 123456789, "hello_world".to_string(), 420);
+        let input = input("sender", "hashy", 10.0, 123456789);
+        let output = TransactionAmount::new(9.5, "receiver".to_string(), "hashy".to_string(), 123456789, 0);
+
+        transaction.set_transaction_amounts(vec![input, output]);
+
+        assert_eq!(transaction.input_total(), 10.0);
+        assert_eq!(transaction.output_total(), 9.5);
+        assert_eq!(transaction.fee(), 0.5);
+    }
+
+    fn spend(address: &str, sequence: i64, witness: Vec<String>) -> TransactionAmount {
+        let mut input = input(address, "hashy", 1.0, 123456789);
+        input.set_sequence(Some(sequence));
+        input.set_witness(witness);
+        input
+    }
+
+    #[test]
+    fn timelock_and_rbf_test() {
+        let mut transaction = Transaction::new("hashy".to_string(), false, 123456789, "hello_world".to_string(), 420);
+        transaction.set_locktime(Some(800000));
+        transaction.set_transaction_amounts(vec![spend("1Sender", 0xffffffff, vec![])]);
+
+        // Every sequence is final, so the locktime is disabled
+        assert_eq!(transaction.absolute_timelock(), None);
+        assert!(!transaction.signals_rbf());
+        assert!(!transaction.is_segwit());
+
+        transaction.set_transaction_amounts(vec![spend("1Sender", 0xfffffffd, vec![]), spend("1Sender", 0x00400010, vec![])]);
+        assert_eq!(transaction.absolute_timelock(), Some(Timelock::Height(800000)));
+        assert!(transaction.signals_rbf());
+        assert_eq!(transaction.transaction_amounts()[0].relative_timelock(), None);
+        assert_eq!(transaction.transaction_amounts()[1].relative_timelock(), Some(Timelock::Time(16 * 512)));
+        assert_eq!(spend("1Sender", 144, vec![]).relative_timelock(), Some(Timelock::Height(144)));
+
+        transaction.set_locktime(Some(1700000000));
+        assert_eq!(transaction.absolute_timelock(), Some(Timelock::Time(1700000000)));
+    }
+
+    #[test]
+    fn taproot_spend_test() {
+        let taproot = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+        let signature = "ab".repeat(64);
+        let keypath = spend(taproot, 0xffffffff, vec![signature.clone()]);
+        assert!(keypath.is_segwit());
+        assert!(keypath.is_taproot_keypath());
+        assert!(!keypath.is_taproot_scriptpath());
+
+        // Signature, leaf script, control block with one merkle branch and an annex
+        let control_block = format!("c0{}", "11".repeat(64));
+        let scriptpath = spend(taproot, 0xffffffff, vec![signature.clone(), "20".repeat(34), control_block, "50aa".to_string()]);
+        assert!(scriptpath.is_taproot_scriptpath());
+        assert!(!scriptpath.is_taproot_keypath());
+
+        // A P2WPKH spend is a signature and a public key
+        let p2wpkh = spend("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", 0xffffffff, vec![signature.clone(), "02".repeat(33)]);
+        assert!(p2wpkh.is_segwit());
+        assert!(!p2wpkh.is_taproot_keypath());
+        assert!(!p2wpkh.is_taproot_scriptpath());
+
+        // The same witness on an address that can't be classified, the public key isn't a control block
+        let unknown = spend("unclassified", 0xffffffff, vec![format!("30{}01", signature), "02".repeat(33)]);
+        assert!(!unknown.is_taproot_keypath());
+        assert!(!unknown.is_taproot_scriptpath());
+    }
+
+    #[test]
+    fn script_data_serde_test() {
+        let output = TransactionAmount::new(1.0, "address".to_string(), "hashy".to_string(), 123456789, 0);
+        let json = serde_json::to_value(&output).unwrap();
+        assert!(json.get("witness").is_none());
+        assert!(json.get("sequence").is_none());
+
+        let mut input = spend("address", 0xfffffffe, vec!["aa".to_string()]);
+        input.set_script_sig(Some("0014aa".to_string()));
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(json["witness"][0], "aa");
+        let input: TransactionAmount = serde_json::from_value(json).unwrap();
+        assert_eq!(input.sequence(), Some(0xfffffffe));
+        assert_eq!(input.script_sig(), &Some("0014aa".to_string()));
+
+        // Final sequence and the largest locktime don't fit a signed 32 bit GraphQL Int
+        let mut transaction = Transaction::new("hashy".to_string(), false, 123456789, "hello_world".to_string(), 420);
+        transaction.set_locktime(Some(0xffffffff));
+        transaction.set_transaction_amounts(vec![spend("address", 0xffffffff, vec![])]);
+        let json = serde_json::to_value(&transaction).unwrap();
+        assert_eq!(json["locktime"], 4294967295i64);
+        assert_eq!(json["transaction_amount_collection"][0]["sequence"], 4294967295i64);
+        let transaction: Transaction = serde_json::from_value(json).unwrap();
+        assert_eq!(transaction.locktime(), Some(0xffffffff));
+        assert_eq!(transaction.transaction_amounts()[0].sequence(), Some(0xffffffff));
+
+        let fetched: TransactionAmount = serde_json::from_str(r#"{"amount": 1.0, "address_hash": "address", "transaction_hash": "hashy",
+            "date": 123456789, "index": 0, "vin_index": -1, "script_sig": null, "witness": null, "sequence": null}"#).unwrap();
+        assert!(fetched.witness().is_empty());
+    }
+
     #[test]
     fn implements_devii_trait() {
         let block = Block::new("hello_world".to_string(), 123456789, 420);
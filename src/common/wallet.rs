@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use named_type_derive::*;
+use named_type::NamedType;
+use getset::{CopyGetters, Getters, Setters};
+use chrono::{Utc, SecondsFormat};
+use devii::devii::DeviiTrait;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::common::address::Address;
+use crate::common::blockchain::BlockChainNames;
+use crate::common::transaction::{to_satoshis, TransactionAmount, SATOSHIS_PER_COIN};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Getters)]
+pub struct WalletMember {
+    #[getset(get = "pub")]
+    blockchain_name: BlockChainNames,
+
+    #[getset(get = "pub")]
+    address_hash: String
+}
+
+// A customer wallet made of many addresses, possibly across chains. Only the membership
+// is stored, the Address records are attached after fetching them.
+#[derive(Serialize, Deserialize, Debug, Clone, NamedType, Default, Getters, Setters)]
+pub struct Wallet {
+    #[getset(get = "pub")]
+    id: String, // Primary Key
+
+    #[getset(get = "pub", set = "pub")]
+    name: String,
+
+    #[getset(get = "pub", set = "pub")]
+    last_updated: String,
+
+    #[getset(get = "pub")]
+    #[serde(default)]
+    members: Vec<WalletMember>,
+
+    #[serde(skip)]
+    addresses: HashMap<BlockChainNames, Vec<Address>>
+}
+
+// Net effect of one transaction on the wallet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct WalletTransaction {
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get_copy = "pub")]
+    date: i64,
+
+    #[getset(get_copy = "pub")]
+    received: f64,
+
+    #[getset(get_copy = "pub")]
+    sent: f64,
+
+    // Coins moved between two wallet addresses, left out of received and sent
+    #[getset(get_copy = "pub")]
+    internal: f64
+}
+
+impl WalletTransaction {
+    pub fn net(&self) -> f64 {
+        self.received - self.sent
+    }
+
+    pub fn is_internal(&self) -> bool {
+        self.internal > 0.0 && self.received == 0.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct ChainPortfolio {
+    #[getset(get = "pub")]
+    blockchain_name: BlockChainNames,
+
+    #[getset(get_copy = "pub")]
+    address_count: i64,
+
+    #[getset(get_copy = "pub")]
+    coin_total: f64,
+
+    #[getset(get_copy = "pub")]
+    first_transaction: i64,
+
+    #[getset(get_copy = "pub")]
+    last_transaction: i64
+}
+
+impl Wallet {
+    pub fn new(id: String, name: String) -> Self {
+        Wallet {
+            id,
+            name,
+            last_updated: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            members: vec![],
+            addresses: HashMap::new()
+        }
+    }
+
+    pub fn add_member(&mut self, blockchain_name: BlockChainNames, address_hash: String) -> &mut Self {
+        let member = WalletMember { blockchain_name, address_hash };
+        if !self.members.contains(&member) {
+            self.members.push(member);
+        }
+        self
+    }
+
+    // Adds the address as a member if it isn't one already, replacing an attached copy
+    pub fn add_address(&mut self, blockchain_name: BlockChainNames, address: Address) -> &mut Self {
+        self.add_member(blockchain_name.clone(), address.hash().clone());
+        let addresses = self.addresses.entry(blockchain_name).or_default();
+        addresses.retain(|a| a.hash() != address.hash());
+        addresses.push(address);
+        self
+    }
+
+    pub fn contains(&self, blockchain_name: &BlockChainNames, address_hash: &str) -> bool {
+        self.members.iter().any(|m| &m.blockchain_name == blockchain_name && m.address_hash == address_hash)
+    }
+
+    pub fn addresses(&self, blockchain_name: &BlockChainNames) -> &[Address] {
+        self.addresses.get(blockchain_name).map(|a| a.as_slice()).unwrap_or(&[])
+    }
+
+    // Members whose Address hasn't been attached yet
+    pub fn missing_addresses(&self) -> Vec<&WalletMember> {
+        self.members.iter()
+            .filter(|m| !self.addresses(&m.blockchain_name).iter().any(|a| a.hash() == &m.address_hash))
+            .collect()
+    }
+
+    pub fn total_balance(&self, blockchain_name: &BlockChainNames) -> f64 {
+        let satoshis: i64 = self.addresses(blockchain_name).iter().map(|a| to_satoshis(a.coin_total())).sum();
+        satoshis as f64 / SATOSHIS_PER_COIN
+    }
+
+    // Coinbase rewards on one chain that can't be spent yet at `tip_height`
+    pub fn immature_balance(&self, blockchain_name: &BlockChainNames, tip_height: i64) -> f64 {
+        self.addresses(blockchain_name).iter().map(|a| a.immature_balance(tip_height, blockchain_name)).sum()
+    }
+
+    // Member amounts on one chain, oldest first, with amounts seen through several
+    // members (or attached twice) only kept once
+    pub fn history(&self, blockchain_name: &BlockChainNames) -> Vec<TransactionAmount> {
+        let mut seen = HashSet::new();
+        let mut history: Vec<TransactionAmount> = self.addresses(blockchain_name).iter()
+            .flat_map(|a| a.transactions())
+            .filter(|a| seen.insert((a.transaction_hash().clone(), a.address_hash().clone(), a.index(), a.vin_hash().clone(), a.vin_index())))
+            .cloned()
+            .collect();
+        history.sort_by(|a, b| a.date().cmp(&b.date()).then_with(|| a.transaction_hash().cmp(b.transaction_hash())));
+        history
+    }
+
+    // One entry per transaction. When coins leave one member and arrive at another the
+    // matched part is reported as internal rather than as sent and received.
+    pub fn transactions(&self, blockchain_name: &BlockChainNames) -> Vec<WalletTransaction> {
+        let mut totals: BTreeMap<(i64, String), (i64, i64)> = BTreeMap::new();
+        for amount in self.history(blockchain_name) {
+            let (received, sent) = totals.entry((amount.date(), amount.transaction_hash().clone())).or_insert((0, 0));
+            if amount.is_input() {
+                *sent += to_satoshis(amount.amount().abs());
+            } else {
+                *received += to_satoshis(amount.amount());
+            }
+        }
+
+        totals.into_iter().map(|((date, transaction_hash), (received, sent))| {
+            let internal = received.min(sent);
+            WalletTransaction {
+                transaction_hash,
+                date,
+                received: (received - internal) as f64 / SATOSHIS_PER_COIN,
+                sent: (sent - internal) as f64 / SATOSHIS_PER_COIN,
+                internal: internal as f64 / SATOSHIS_PER_COIN
+            }
+        }).collect()
+    }
+
+    pub fn portfolio(&self) -> Vec<ChainPortfolio> {
+        BlockChainNames::all().into_iter()
+            .filter(|chain| self.members.iter().any(|m| &m.blockchain_name == chain))
+            .map(|chain| {
+                let addresses = self.addresses(&chain);
+                let first_transaction = addresses.iter().map(|a| a.first_transaction()).filter(|t| *t > 0).min().unwrap_or(0);
+                ChainPortfolio {
+                    address_count: self.members.iter().filter(|m| m.blockchain_name == chain).count() as i64,
+                    coin_total: self.total_balance(&chain),
+                    first_transaction,
+                    last_transaction: addresses.iter().map(|a| a.last_transaction()).max().unwrap_or(0),
+                    blockchain_name: chain
+                }
+            })
+            .collect()
+    }
+}
+
+impl DeviiTrait for Wallet {
+    fn fetch_fields() -> String {
+        format!("{{ id, name, last_updated, members {{ blockchain_name, address_hash }} }}")
+    }
+    fn insert_query(&self, param: String) -> String{
+        format!("create_wallet (input: ${} ){{ id }}", param)
+    }
+    fn input_type(&self) -> String {
+        "walletInput".to_string()
+    }
+    fn graphql_inputs(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+    fn delete_input(&self) -> String {
+        format!("id: \"{}\"", self.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::transaction::TransactionAmount;
+    use crate::common::wallet::*;
+    use crate::test_fixtures::{address, input};
+
+    // alice receives 5, then moves 3 to bob (her change goes back to her) and bob pays out 1
+    fn wallet() -> Wallet {
+        let mut wallet = Wallet::new("wallet_1".to_string(), "Customer".to_string());
+        wallet.add_address(BlockChainNames::Bitcoin, address("alice", vec![
+            TransactionAmount::new(5.0, "alice".to_string(), "deposit".to_string(), 10, 0),
+            input("alice", "move", 5.0, 20),
+            TransactionAmount::new(1.99, "alice".to_string(), "move".to_string(), 20, 1),
+        ]));
+        wallet.add_address(BlockChainNames::Bitcoin, address("bob", vec![
+            TransactionAmount::new(3.0, "bob".to_string(), "move".to_string(), 20, 0),
+            input("bob", "payout", 3.0, 30),
+            TransactionAmount::new(2.0, "bob".to_string(), "payout".to_string(), 30, 1),
+        ]));
+        wallet.add_member(BlockChainNames::Litecoin, "Lcarol".to_string());
+        wallet
+    }
+
+    #[test]
+    fn history_test() {
+        let mut wallet = wallet();
+        // Attaching alice again must not duplicate anything
+        let alice = wallet.addresses(&BlockChainNames::Bitcoin)[0].clone();
+        wallet.add_address(BlockChainNames::Bitcoin, alice);
+
+        assert_eq!(wallet.history(&BlockChainNames::Bitcoin).len(), 6);
+        assert_eq!(wallet.total_balance(&BlockChainNames::Bitcoin), 3.99);
+
+        let transactions = wallet.transactions(&BlockChainNames::Bitcoin);
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(transactions[0].received(), 5.0);
+        assert_eq!(transactions[1].transaction_hash(), &"move".to_string());
+        assert!(transactions[1].is_internal());
+        assert_eq!(transactions[1].internal(), 4.99);
+        assert_eq!(transactions[1].net(), -0.01);
+        assert_eq!(transactions[2].sent(), 1.0);
+    }
+
+    #[test]
+    fn portfolio_test() {
+        let wallet = wallet();
+        let portfolio = wallet.portfolio();
+
+        assert_eq!(portfolio.len(), 2);
+        assert_eq!(portfolio[0].blockchain_name(), &BlockChainNames::Bitcoin);
+        assert_eq!(portfolio[0].address_count(), 2);
+        assert_eq!(portfolio[0].first_transaction(), 10);
+        assert_eq!(portfolio[0].last_transaction(), 30);
+        assert_eq!(portfolio[1].blockchain_name(), &BlockChainNames::Litecoin);
+        assert_eq!(portfolio[1].coin_total(), 0.0);
+        assert_eq!(wallet.missing_addresses().len(), 1);
+    }
+
+    #[test]
+    fn serde_test() {
+        let wallet = wallet();
+        let json = serde_json::to_value(&wallet).unwrap();
+        assert_eq!(json["members"][2]["blockchain_name"], "Litecoin");
+        assert!(json.get("addresses").is_none());
+
+        let wallet: Wallet = serde_json::from_value(json).unwrap();
+        assert!(wallet.contains(&BlockChainNames::Bitcoin, "bob"));
+        assert!(wallet.addresses(&BlockChainNames::Bitcoin).is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+use easy_error::bail;
+use secp256k1::{PublicKey, Scalar, Secp256k1};
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use std::error::Error;
+use std::fmt;
+
+use crate::keys::encoding::{hash160, ScriptType};
+
+pub(crate) const HARDENED: u32 = 0x8000_0000;
+
+// Version bytes of the serialized extended public keys we recognise
+const VERSIONS: [([u8; 4], Option<ScriptType>); 7] = [
+    ([0x04, 0x88, 0xb2, 0x1e], None), // xpub, BIP44 or BIP86
+    ([0x04, 0x9d, 0x7c, 0xb2], Some(ScriptType::P2SHP2WPKH)), // ypub
+    ([0x04, 0xb2, 0x47, 0x46], Some(ScriptType::P2WPKH)), // zpub
+    ([0x01, 0x9d, 0xa4, 0x62], Some(ScriptType::P2PKH)), // Ltub
+    ([0x01, 0xb2, 0x6e, 0xf6], Some(ScriptType::P2SHP2WPKH)), // Mtub
+    ([0x02, 0xfa, 0xca, 0xfd], Some(ScriptType::P2PKH)), // dgub
+    ([0x02, 0xfe, 0x52, 0xcc], Some(ScriptType::P2PKH)), // drkp
+];
+
+// BIP32 extended public key. Only non hardened children can be derived from it,
+// so it's normally exported at the account level (m/purpose'/coin'/account').
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    public_key: PublicKey
+}
+
+impl ExtendedPublicKey {
+    pub fn parse(encoded: &str) -> Result<Self, Box<dyn Error>> {
+        let data = bs58::decode(encoded.trim()).with_check(None).into_vec()?;
+        if data.len() != 78 {
+            bail!("Extended public key is {} bytes, expected 78", data.len());
+        }
+        let version: [u8; 4] = data[0..4].try_into()?;
+        if !VERSIONS.iter().any(|(known, _)| known == &version) {
+            bail!("Unknown extended public key version {:02x?}", version);
+        }
+        Ok(ExtendedPublicKey {
+            version,
+            depth: data[4],
+            parent_fingerprint: data[5..9].try_into()?,
+            child_number: u32::from_be_bytes(data[9..13].try_into()?),
+            chain_code: data[13..45].try_into()?,
+            public_key: PublicKey::from_slice(&data[45..78])?
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        let mut data = Vec::with_capacity(78);
+        data.extend_from_slice(&self.version);
+        data.push(self.depth);
+        data.extend_from_slice(&self.parent_fingerprint);
+        data.extend_from_slice(&self.child_number.to_be_bytes());
+        data.extend_from_slice(&self.chain_code);
+        data.extend_from_slice(&self.public_key.serialize());
+        bs58::encode(data).with_check().into_string()
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        hash160(&self.public_key.serialize())[0..4].try_into().unwrap()
+    }
+
+    // Script type implied by the version bytes, None for plain xpubs which are used
+    // for both BIP44 and BIP86
+    pub fn script_type(&self) -> Option<ScriptType> {
+        VERSIONS.iter().find(|(version, _)| version == &self.version).and_then(|(_, script_type)| *script_type)
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self, Box<dyn Error>> {
+        match self.try_derive_child(index)? {
+            Some(child) => Ok(child),
+            None => bail!("Child {} is not a valid key, BIP32 skips to the next index", index),
+        }
+    }
+
+    // None when the index gives an invalid key (probability below 1 in 2^127)
+    pub fn try_derive_child(&self, index: u32) -> Result<Option<Self>, Box<dyn Error>> {
+        if index >= HARDENED {
+            bail!("Can't derive hardened child {}' from a public key", index - HARDENED);
+        }
+        if self.depth == u8::MAX {
+            bail!("Maximum derivation depth reached");
+        }
+        let mut mac = Hmac::<Sha512>::new_from_slice(&self.chain_code)?;
+        mac.update(&self.public_key.serialize());
+        mac.update(&index.to_be_bytes());
+        let result = mac.finalize().into_bytes();
+
+        let Ok(tweak) = Scalar::from_be_bytes(result[0..32].try_into()?) else { return Ok(None) };
+        let secp = Secp256k1::verification_only();
+        let Ok(public_key) = self.public_key.add_exp_tweak(&secp, &tweak) else { return Ok(None) };
+        Ok(Some(ExtendedPublicKey {
+            version: self.version,
+            depth: self.depth + 1,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code: result[32..64].try_into()?,
+            public_key
+        }))
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self, Box<dyn Error>> {
+        let mut key = self.clone();
+        for index in path {
+            key = key.derive_child(*index)?;
+        }
+        Ok(key)
+    }
+}
+
+impl fmt::Display for ExtendedPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keys::bip32::*;
+
+    // BIP32 test vector 2
+    const MASTER: &str = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+    const CHILD_0: &str = "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH";
+
+    #[test]
+    fn parse_and_encode_test() {
+        let master = ExtendedPublicKey::parse(MASTER).unwrap();
+        assert_eq!(master.depth(), 0);
+        assert_eq!(master.to_string(), MASTER);
+        assert_eq!(master.script_type(), None);
+
+        assert!(ExtendedPublicKey::parse("xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduC").is_err());
+
+        // Same key with testnet tpub version bytes
+        let mut tpub = master.clone();
+        tpub.version = [0x04, 0x35, 0x87, 0xcf];
+        assert!(tpub.to_string().starts_with("tpub"));
+        assert!(ExtendedPublicKey::parse(&tpub.to_string()).is_err());
+    }
+
+    #[test]
+    fn derive_test() {
+        let master = ExtendedPublicKey::parse(MASTER).unwrap();
+        let child = master.derive_child(0).unwrap();
+        assert_eq!(child.to_string(), CHILD_0);
+        assert_eq!(child.child_number(), 0);
+        assert_eq!(master.derive_path(&[0]).unwrap(), child);
+        assert!(master.derive_child(HARDENED).is_err());
+    }
+}
@@ -0,0 +1,530 @@
+use serde::{Deserialize, Serialize};
+use easy_error::bail;
+use getset::{CopyGetters, Getters};
+use secp256k1::PublicKey;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use crate::common::block::Block;
+use crate::common::blockchain::BlockChainNames;
+use crate::common::transaction::{decode_hex, encode_hex, TransactionAmount};
+use crate::keys::bip32::{ExtendedPublicKey, HARDENED};
+use crate::keys::encoding::{hash160, script_address, taproot_output_key};
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// BIP380 checksum of a descriptor string (without the '#')
+pub fn descriptor_checksum(descriptor: &str) -> Result<String, Box<dyn Error>> {
+    let mut c: u64 = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = match INPUT_CHARSET.find(ch) {
+            Some(position) => position as u64,
+            None => bail!("Invalid character {:?} in descriptor", ch),
+        };
+        c = checksum_polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = checksum_polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = checksum_polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = checksum_polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..8).map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char).collect())
+}
+
+fn checksum_polymod(c: u64, value: u64) -> u64 {
+    const GENERATORS: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+    let top = c >> 35;
+    let mut c = ((c & 0x07ffffffff) << 5) ^ value;
+    for (i, generator) in GENERATORS.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+// Derivation step, with an h, H or ' suffix for hardened steps
+fn parse_step(step: &str) -> Result<u32, Box<dyn Error>> {
+    let (number, hardened) = match step.strip_suffix(['h', 'H', '\'']) {
+        Some(number) => (number, true),
+        None => (step, false),
+    };
+    let index: u32 = number.parse()?;
+    if index >= HARDENED {
+        bail!("Derivation step {} is out of range", step);
+    }
+    Ok(if hardened { index | HARDENED } else { index })
+}
+
+// Master key fingerprint and path from the [d34db33f/84h/0h/0h] prefix of a key
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+pub struct KeyOrigin {
+    #[getset(get = "pub")]
+    fingerprint: [u8; 4],
+
+    #[getset(get = "pub")]
+    path: Vec<u32>
+}
+
+impl KeyOrigin {
+    fn parse(origin: &str) -> Result<Self, Box<dyn Error>> {
+        let mut steps = origin.split('/');
+        let fingerprint = match steps.next().and_then(decode_hex) {
+            Some(bytes) if bytes.len() == 4 => bytes.try_into().unwrap(),
+            _ => bail!("Invalid key origin fingerprint in [{}]", origin),
+        };
+        Ok(KeyOrigin { fingerprint, path: steps.map(parse_step).collect::<Result<_, _>>()? })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorKey {
+    Single(PublicKey),
+    // Already derived along the fixed part of the path, a wildcard adds one more
+    // non hardened step per index
+    Extended { xpub: ExtendedPublicKey, wildcard: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+pub struct KeyExpression {
+    #[getset(get = "pub")]
+    origin: Option<KeyOrigin>,
+
+    #[getset(get = "pub")]
+    key: DescriptorKey
+}
+
+impl KeyExpression {
+    // tr() also takes 32 byte x-only keys, which are lifted to the even y point
+    fn parse(expression: &str, x_only: bool) -> Result<Self, Box<dyn Error>> {
+        let (origin, key) = match expression.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((origin, key)) => (Some(KeyOrigin::parse(origin)?), key),
+                None => bail!("Unterminated key origin in {}", expression),
+            },
+            None => (None, expression),
+        };
+
+        if let Some(bytes) = decode_hex(key) {
+            let public_key = match bytes.len() {
+                33 => PublicKey::from_slice(&bytes)?,
+                32 if x_only => PublicKey::from_slice(&[&[0x02][..], &bytes].concat())?,
+                65 => bail!("Uncompressed keys aren't supported"),
+                _ => bail!("Invalid public key {}", key),
+            };
+            return Ok(KeyExpression { origin, key: DescriptorKey::Single(public_key) })
+        }
+
+        let mut steps: Vec<&str> = key.split('/').collect();
+        let xpub = ExtendedPublicKey::parse(steps.remove(0))?;
+        let wildcard = match steps.last() {
+            Some(&"*") => {
+                steps.pop();
+                true
+            },
+            Some(&("*h" | "*H" | "*'")) => bail!("Hardened wildcards can't be derived from a public key"),
+            _ => false,
+        };
+        let path = steps.into_iter().map(parse_step).collect::<Result<Vec<u32>, _>>()?;
+        Ok(KeyExpression { origin, key: DescriptorKey::Extended { xpub: xpub.derive_path(&path)?, wildcard } })
+    }
+
+    pub fn is_range(&self) -> bool {
+        matches!(self.key, DescriptorKey::Extended { wildcard: true, .. })
+    }
+
+    pub fn derive(&self, index: u32) -> Result<PublicKey, Box<dyn Error>> {
+        match &self.key {
+            DescriptorKey::Single(public_key) => Ok(*public_key),
+            DescriptorKey::Extended { xpub, wildcard: true } => Ok(*xpub.derive_child(index)?.public_key()),
+            DescriptorKey::Extended { xpub, wildcard: false } => Ok(*xpub.public_key()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fragment {
+    Pkh(KeyExpression),
+    Wpkh(KeyExpression),
+    Sh(Box<Fragment>),
+    Wsh(Box<Fragment>),
+    Multi { threshold: usize, keys: Vec<KeyExpression>, sorted: bool },
+    Tr(KeyExpression), // key path only
+}
+
+// Where a fragment appears, which limits what it may contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Top,
+    Sh,
+    Wsh,
+}
+
+// Splits on the commas that aren't nested inside another expression
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, ch) in args.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+impl Fragment {
+    fn parse(expression: &str, context: Context) -> Result<Self, Box<dyn Error>> {
+        let (name, args) = match expression.split_once('(') {
+            Some((name, rest)) if rest.ends_with(')') => (name, split_args(&rest[..rest.len() - 1])),
+            _ => bail!("Expected a script expression, found {}", expression),
+        };
+        let single_arg = || -> Result<&str, Box<dyn Error>> {
+            match args.as_slice() {
+                [arg] => Ok(*arg),
+                _ => bail!("{}() takes a single argument", name),
+            }
+        };
+
+        match (name, context) {
+            ("pkh", _) => Ok(Fragment::Pkh(KeyExpression::parse(single_arg()?, false)?)),
+            ("wpkh", Context::Top | Context::Sh) => Ok(Fragment::Wpkh(KeyExpression::parse(single_arg()?, false)?)),
+            ("sh", Context::Top) => Ok(Fragment::Sh(Box::new(Fragment::parse(single_arg()?, Context::Sh)?))),
+            ("wsh", Context::Top | Context::Sh) => Ok(Fragment::Wsh(Box::new(Fragment::parse(single_arg()?, Context::Wsh)?))),
+            // Bare multisig has no address, so it's only accepted inside sh() or wsh()
+            ("multi" | "sortedmulti", Context::Sh | Context::Wsh) => {
+                if args.len() < 2 {
+                    bail!("{}() needs a threshold and at least one key", name);
+                }
+                let threshold: usize = args[0].parse()?;
+                let keys = args[1..].iter().map(|key| KeyExpression::parse(key, false)).collect::<Result<Vec<_>, _>>()?;
+                if threshold == 0 || threshold > keys.len() {
+                    bail!("Threshold {} is out of range for {} keys", threshold, keys.len());
+                }
+                // A P2SH redeem script is limited to 520 bytes, which fits 15 compressed keys
+                let max_keys = if context == Context::Sh { 15 } else { 16 };
+                if keys.len() > max_keys {
+                    bail!("At most {} multisig keys are supported in {}", max_keys, if context == Context::Sh { "sh()" } else { "wsh()" });
+                }
+                Ok(Fragment::Multi { threshold, keys, sorted: name == "sortedmulti" })
+            },
+            ("tr", Context::Top) => {
+                if args.len() > 1 {
+                    bail!("Taproot script trees aren't supported");
+                }
+                Ok(Fragment::Tr(KeyExpression::parse(single_arg()?, true)?))
+            },
+            _ => bail!("{}() isn't supported here", name),
+        }
+    }
+
+    pub fn keys(&self) -> Vec<&KeyExpression> {
+        match self {
+            Fragment::Pkh(key) | Fragment::Wpkh(key) | Fragment::Tr(key) => vec![key],
+            Fragment::Sh(inner) | Fragment::Wsh(inner) => inner.keys(),
+            Fragment::Multi { keys, .. } => keys.iter().collect(),
+        }
+    }
+
+    pub fn script(&self, index: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            Fragment::Pkh(key) => [&[0x76, 0xa9, 0x14][..], &hash160(&key.derive(index)?.serialize()), &[0x88, 0xac]].concat(),
+            Fragment::Wpkh(key) => [&[0x00, 0x14][..], &hash160(&key.derive(index)?.serialize())].concat(),
+            Fragment::Sh(inner) => [&[0xa9, 0x14][..], &hash160(&inner.script(index)?), &[0x87]].concat(),
+            Fragment::Wsh(inner) => [&[0x00, 0x20][..], &Sha256::digest(inner.script(index)?)].concat(),
+            Fragment::Multi { threshold, keys, sorted } => {
+                let mut public_keys = keys.iter().map(|key| Ok(key.derive(index)?.serialize())).collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+                if *sorted {
+                    public_keys.sort();
+                }
+                // OP_k <keys> OP_n OP_CHECKMULTISIG
+                let mut script = vec![0x50 + *threshold as u8];
+                for public_key in &public_keys {
+                    script.push(0x21);
+                    script.extend_from_slice(public_key);
+                }
+                script.extend_from_slice(&[0x50 + public_keys.len() as u8, 0xae]);
+                script
+            },
+            Fragment::Tr(key) => [&[0x51, 0x20][..], &taproot_output_key(&key.derive(index)?)?].concat(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct DescriptorOutput {
+    #[getset(get_copy = "pub")]
+    index: u32,
+
+    #[getset(get = "pub")]
+    script_pubkey: String, // hex
+
+    #[getset(get = "pub")]
+    address_hash: String
+}
+
+// Output descriptor as exported by watch-only wallets, e.g.
+// wpkh([d34db33f/84h/0h/0h]xpub.../0/*)#checksum
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+pub struct Descriptor {
+    #[getset(get = "pub")]
+    fragment: Fragment,
+
+    // Without the checksum
+    body: String,
+
+    #[getset(get = "pub")]
+    checksum: String
+}
+
+impl Descriptor {
+    // The checksum is optional, but must match when given
+    pub fn parse(descriptor: &str) -> Result<Self, Box<dyn Error>> {
+        let (body, given) = match descriptor.trim().split_once('#') {
+            Some((body, checksum)) => (body, Some(checksum)),
+            None => (descriptor.trim(), None),
+        };
+        let checksum = descriptor_checksum(body)?;
+        if let Some(given) = given {
+            if given != checksum {
+                bail!("Descriptor checksum {} doesn't match, expected {}", given, checksum);
+            }
+        }
+        Ok(Descriptor { fragment: Fragment::parse(body, Context::Top)?, body: body.to_string(), checksum })
+    }
+
+    pub fn is_range(&self) -> bool {
+        self.fragment.keys().iter().any(|key| key.is_range())
+    }
+
+    pub fn script_pubkey(&self, index: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.fragment.script(index)
+    }
+
+    pub fn address(&self, chain: &BlockChainNames, index: u32) -> Result<String, Box<dyn Error>> {
+        script_address(chain, &self.script_pubkey(index)?)
+    }
+
+    // Descriptors without a wildcard expand to their single output
+    pub fn expand(&self, chain: &BlockChainNames, range: Range<u32>) -> Result<Vec<DescriptorOutput>, Box<dyn Error>> {
+        let range = if self.is_range() { range } else { 0..1 };
+        range.map(|index| {
+            let script = self.script_pubkey(index)?;
+            Ok(DescriptorOutput { index, address_hash: script_address(chain, &script)?, script_pubkey: encode_hex(&script) })
+        }).collect()
+    }
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}", self.body, self.checksum)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct DescriptorMatch {
+    #[getset(get_copy = "pub")]
+    block_height: i64,
+
+    #[getset(get = "pub")]
+    transaction_hash: String,
+
+    #[getset(get = "pub")]
+    address_hash: String,
+
+    #[getset(get_copy = "pub")]
+    derivation_index: u32,
+
+    #[getset(get_copy = "pub")]
+    amount: f64 // negative for spends
+}
+
+// Matches ingested amounts against a descriptor's addresses. Ranged descriptors are
+// derived `lookahead` indexes past the highest match so new addresses keep matching.
+#[derive(Debug, Clone)]
+pub struct DescriptorWatcher {
+    descriptor: Descriptor,
+    chain: BlockChainNames,
+    lookahead: u32,
+    derived: u32,
+    addresses: HashMap<String, u32>
+}
+
+impl DescriptorWatcher {
+    pub fn new(descriptor: Descriptor, chain: BlockChainNames, lookahead: u32) -> Result<Self, Box<dyn Error>> {
+        let mut watcher = DescriptorWatcher { descriptor, chain, lookahead: lookahead.max(1), derived: 0, addresses: HashMap::new() };
+        watcher.derive_to(watcher.lookahead)?;
+        Ok(watcher)
+    }
+
+    pub fn descriptor(&self) -> &Descriptor {
+        &self.descriptor
+    }
+
+    // Number of indexes derived so far
+    pub fn derived(&self) -> u32 {
+        self.derived
+    }
+
+    fn derive_to(&mut self, end: u32) -> Result<(), Box<dyn Error>> {
+        if self.derived > 0 && !self.descriptor.is_range() {
+            return Ok(())
+        }
+        for output in self.descriptor.expand(&self.chain, self.derived..end)? {
+            self.addresses.insert(output.address_hash, output.index);
+        }
+        self.derived = end;
+        Ok(())
+    }
+
+    pub fn address_index(&self, address_hash: &str) -> Option<u32> {
+        self.addresses.get(address_hash).copied()
+    }
+
+    pub fn match_amount(&mut self, amount: &TransactionAmount) -> Result<Option<u32>, Box<dyn Error>> {
+        let index = match self.address_index(amount.address_hash()) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let end = index.saturating_add(1).saturating_add(self.lookahead);
+        if end > self.derived {
+            self.derive_to(end)?;
+        }
+        Ok(Some(index))
+    }
+
+    pub fn match_block(&mut self, block: &Block) -> Result<Vec<DescriptorMatch>, Box<dyn Error>> {
+        let mut matches = vec![];
+        for transaction in block.transactions() {
+            for amount in transaction.transaction_amounts() {
+                if let Some(derivation_index) = self.match_amount(amount)? {
+                    matches.push(DescriptorMatch {
+                        block_height: block.height(),
+                        transaction_hash: transaction.hash().clone(),
+                        address_hash: amount.address_hash().clone(),
+                        derivation_index,
+                        amount: amount.amount()
+                    });
+                }
+            }
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::block::Block;
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::transaction::{encode_hex, Transaction, TransactionAmount};
+    use crate::keys::descriptor::*;
+
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    const BIP86_XPUB: &str = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+
+    fn script(descriptor: &str) -> String {
+        encode_hex(&Descriptor::parse(descriptor).unwrap().script_pubkey(0).unwrap())
+    }
+
+    #[test]
+    fn checksum_test() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+
+        let body = "pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)";
+        let descriptor = Descriptor::parse(body).unwrap();
+        let with_checksum = descriptor.to_string();
+        assert_eq!(Descriptor::parse(&with_checksum).unwrap(), descriptor);
+        assert!(Descriptor::parse(&format!("{}#qqqqqqqq", body)).is_err());
+        assert!(descriptor_checksum("pkh(é)").is_err());
+    }
+
+    #[test]
+    fn script_test() {
+        assert_eq!(script("pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)"), "76a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac");
+        assert_eq!(script("wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)"), "00147dd65592d0ab2fe0d0257d571abf032cd9db93dc");
+        assert_eq!(script("sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))"), "a914a6a8b030a38762f4c1f5cbe387b61a3c5da5cd2687");
+
+        let keys = "03a0434d9e47f3c86235477c7b1ae6ae5d3442d49b1943c2b752a68e2a47e247c7,03774ae7f858a9411e5ef4246b70c65aac5649980be5c17891bbec17895da008cb,03d01115d548e7561b15c38f004d734633687cf4419620095bc5b0f47070afe85a";
+        assert_eq!(script(&format!("wsh(multi(2,{}))", keys)), "0020773d709598b76c4e3b575c08aad40658963f9322affc0f8c28d1d9a68d0c944a");
+        assert_eq!(script(&format!("wsh(sortedmulti(2,{}))", keys)), "0020ec566436042722eb23f0a043b896b10fe2443f4d15cc606dcfc14fa48187fa25");
+
+        assert!(Descriptor::parse("wsh(wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9))").is_err());
+        assert!(Descriptor::parse(&format!("sh(multi(4,{}))", keys)).is_err());
+        assert!(Descriptor::parse(&format!("multi(2,{})", keys)).is_err());
+        assert!(Descriptor::parse(&format!("sortedmulti(2,{})", keys)).is_err());
+
+        let sixteen = vec![&keys[..66]; 16].join(",");
+        assert!(Descriptor::parse(&format!("sh(multi(2,{}))", sixteen)).is_err());
+        assert!(Descriptor::parse(&format!("sh(multi(2,{}))", &sixteen[67..])).is_ok());
+        assert!(Descriptor::parse(&format!("wsh(multi(2,{}))", sixteen)).is_ok());
+        assert!(Descriptor::parse(&format!("wpkh({}/0h/*)", ZPUB)).is_err());
+    }
+
+    #[test]
+    fn expand_test() {
+        let wpkh = Descriptor::parse(&format!("wpkh([73c5da0a/84h/0h/0h]{}/0/*)", ZPUB)).unwrap();
+        assert!(wpkh.is_range());
+        let origin = wpkh.fragment().keys()[0].origin().clone().unwrap();
+        assert_eq!(origin.path(), &vec![84 | HARDENED, HARDENED, HARDENED]);
+
+        let outputs = wpkh.expand(&BlockChainNames::Bitcoin, 0..2).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].address_hash(), &"bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu".to_string());
+        assert_eq!(outputs[1].address_hash(), &"bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g".to_string());
+
+        let tr = Descriptor::parse(&format!("tr({}/0/*)", BIP86_XPUB)).unwrap();
+        assert_eq!(tr.address(&BlockChainNames::Bitcoin, 0).unwrap(), "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+
+        let single = Descriptor::parse("pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)").unwrap();
+        assert_eq!(single.expand(&BlockChainNames::Bitcoin, 0..5).unwrap().len(), 1);
+        assert_eq!(single.address(&BlockChainNames::Bitcoin, 0).unwrap(), "1cMh228HTCiwS8ZsaakH8A8wze1JR5ZsP");
+    }
+
+    #[test]
+    fn watcher_test() {
+        let descriptor = Descriptor::parse(&format!("wpkh({}/0/*)", ZPUB)).unwrap();
+        let far = descriptor.address(&BlockChainNames::Bitcoin, 6).unwrap();
+        let near = descriptor.address(&BlockChainNames::Bitcoin, 3).unwrap();
+        let mut watcher = DescriptorWatcher::new(descriptor, BlockChainNames::Bitcoin, 5).unwrap();
+        assert_eq!(watcher.address_index(&far), None);
+
+        let mut block = Block::new("blocky_hash".to_string(), 100, 7);
+        let mut transaction = Transaction::new_from_block("hashy".to_string(), false, &block);
+        transaction.set_transaction_amounts(vec![
+            TransactionAmount::new(1.0, near.clone(), "hashy".to_string(), 100, 0),
+            TransactionAmount::new(2.0, far.clone(), "hashy".to_string(), 100, 1),
+            TransactionAmount::new(3.0, "1Stranger".to_string(), "hashy".to_string(), 100, 2),
+        ]);
+        block.set_transactions(vec![transaction]);
+
+        // Matching index 3 derives up to index 8, so index 6 in the same block matches too
+        let matches = watcher.match_block(&block).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].derivation_index(), 3);
+        assert_eq!(matches[1].address_hash(), &far);
+        assert_eq!(matches[1].block_height(), 7);
+        assert_eq!(watcher.derived(), 12);
+    }
+}
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use easy_error::bail;
+use secp256k1::{PublicKey, Scalar, Secp256k1};
+use sha2::{Digest, Sha256};
+use ripemd::Ripemd160;
+use bech32::{Fe32, Hrp};
+use tiny_keccak::{Hasher, Keccak};
+use std::error::Error;
+
+use crate::common::address::AddressType;
+use crate::common::blockchain::BlockChainNames;
+
+// Output script a derived key is paid to, named after the BIP defining its derivation path
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptType {
+    P2PKH,      // BIP44
+    P2SHP2WPKH, // BIP49, P2WPKH nested in P2SH
+    P2WPKH,     // BIP84
+    P2TR,       // BIP86, key path only
+}
+
+impl ScriptType {
+    pub fn purpose(&self) -> u32 {
+        match self {
+            ScriptType::P2PKH => 44,
+            ScriptType::P2SHP2WPKH => 49,
+            ScriptType::P2WPKH => 84,
+            ScriptType::P2TR => 86,
+        }
+    }
+
+    pub fn from_purpose(purpose: u32) -> Result<Self, Box<dyn Error>> {
+        match purpose {
+            44 => Ok(ScriptType::P2PKH),
+            49 => Ok(ScriptType::P2SHP2WPKH),
+            84 => Ok(ScriptType::P2WPKH),
+            86 => Ok(ScriptType::P2TR),
+            _ => bail!("Unsupported derivation purpose: {}", purpose),
+        }
+    }
+
+    pub fn address_type(&self) -> AddressType {
+        match self {
+            ScriptType::P2PKH => AddressType::P2PKH,
+            ScriptType::P2SHP2WPKH => AddressType::P2SH,
+            ScriptType::P2WPKH => AddressType::P2WPKH,
+            ScriptType::P2TR => AddressType::P2TR,
+        }
+    }
+}
+
+// Version bytes and prefixes each chain encodes addresses with
+struct ChainParams {
+    p2pkh: u8,
+    p2sh: u8,
+    segwit_hrp: Option<&'static str>,
+}
+
+fn chain_params(chain: &BlockChainNames) -> Option<ChainParams> {
+    match chain {
+        BlockChainNames::Bitcoin => Some(ChainParams { p2pkh: 0x00, p2sh: 0x05, segwit_hrp: Some("bc") }),
+        BlockChainNames::Litecoin => Some(ChainParams { p2pkh: 0x30, p2sh: 0x32, segwit_hrp: Some("ltc") }),
+        BlockChainNames::Dogecoin => Some(ChainParams { p2pkh: 0x1e, p2sh: 0x16, segwit_hrp: None }),
+        BlockChainNames::Dash => Some(ChainParams { p2pkh: 0x4c, p2sh: 0x10, segwit_hrp: None }),
+        // Cashaddr and account chains are encoded separately
+        BlockChainNames::BitcoinCash | BlockChainNames::Ethereum | BlockChainNames::EthereumClassic => None,
+    }
+}
+
+// Address for `public_key` in the form the chain's ingested blocks use
+pub fn encode_address(chain: &BlockChainNames, script_type: ScriptType, public_key: &PublicKey) -> Result<String, Box<dyn Error>> {
+    match chain {
+        BlockChainNames::Ethereum | BlockChainNames::EthereumClassic => return Ok(account_address(public_key)),
+        BlockChainNames::BitcoinCash => {
+            if script_type != ScriptType::P2PKH {
+                bail!("Bitcoin Cash has no {:?} addresses", script_type);
+            }
+            return Ok(cashaddr("bitcoincash", 0, &hash160(&public_key.serialize())))
+        },
+        _ => {}
+    }
+
+    let params = chain_params(chain).unwrap();
+    let segwit_hrp = || -> Result<Hrp, Box<dyn Error>> {
+        match params.segwit_hrp {
+            Some(hrp) => Ok(Hrp::parse(hrp)?),
+            None => bail!("{} has no segwit addresses", chain),
+        }
+    };
+    let key_hash = hash160(&public_key.serialize());
+    match script_type {
+        ScriptType::P2PKH => Ok(base58_address(params.p2pkh, &key_hash)),
+        ScriptType::P2SHP2WPKH => {
+            segwit_hrp()?;
+            let mut redeem_script = vec![0x00, 0x14];
+            redeem_script.extend_from_slice(&key_hash);
+            Ok(base58_address(params.p2sh, &hash160(&redeem_script)))
+        },
+        ScriptType::P2WPKH => Ok(bech32::segwit::encode_v0(segwit_hrp()?, &key_hash)?),
+        ScriptType::P2TR => Ok(bech32::segwit::encode_v1(segwit_hrp()?, &taproot_output_key(public_key)?)?),
+    }
+}
+
+// Address an output script pays to, for the standard script templates
+pub fn script_address(chain: &BlockChainNames, script: &[u8]) -> Result<String, Box<dyn Error>> {
+    let (kind, hash) = match script {
+        [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => (0, hash),
+        [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20 => (1, hash),
+        // Witness programs: version opcode then a single 2 to 40 byte push
+        [version @ (0x00 | 0x51..=0x60), len, program @ ..] if program.len() == *len as usize && (2..=40).contains(len) => {
+            let hrp = match chain_params(chain).and_then(|params| params.segwit_hrp) {
+                Some(hrp) => Hrp::parse(hrp)?,
+                None => bail!("{} has no segwit addresses", chain),
+            };
+            let version = if *version == 0x00 { 0 } else { version - 0x50 };
+            return Ok(bech32::segwit::encode(hrp, Fe32::try_from(version)?, program)?)
+        },
+        _ => bail!("Script has no address form"),
+    };
+
+    match chain {
+        BlockChainNames::Ethereum | BlockChainNames::EthereumClassic => bail!("{} has no script addresses", chain),
+        BlockChainNames::BitcoinCash => Ok(cashaddr("bitcoincash", kind, hash.try_into()?)),
+        _ => {
+            let params = chain_params(chain).unwrap();
+            Ok(base58_address(if kind == 0 { params.p2pkh } else { params.p2sh }, hash))
+        }
+    }
+}
+
+// BIP86 output key: the internal key tweaked by its own TapTweak hash, no script tree
+pub fn taproot_output_key(public_key: &PublicKey) -> Result<[u8; 32], Box<dyn Error>> {
+    let (internal_key, _) = public_key.x_only_public_key();
+    let tweak = tagged_hash("TapTweak", &internal_key.serialize());
+    let secp = Secp256k1::verification_only();
+    let (output_key, _) = internal_key.add_tweak(&secp, &Scalar::from_be_bytes(tweak)?)?;
+    Ok(output_key.serialize())
+}
+
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+pub fn base58_address(version: u8, hash: &[u8]) -> String {
+    let mut payload = vec![version];
+    payload.extend_from_slice(hash);
+    bs58::encode(payload).with_check().into_string()
+}
+
+// EIP-55 mixed case checksum address
+pub fn account_address(public_key: &PublicKey) -> String {
+    let hash = keccak256(&public_key.serialize_uncompressed()[1..]);
+    let hex: String = hash[12..].iter().map(|b| format!("{:02x}", b)).collect();
+    let checksum = keccak256(hex.as_bytes());
+    let mixed: String = hex.chars().enumerate().map(|(i, c)| {
+        let nibble = (checksum[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+        if nibble >= 8 { c.to_ascii_uppercase() } else { c }
+    }).collect();
+    format!("0x{}", mixed)
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut output = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(data);
+    keccak.finalize(&mut output);
+    output
+}
+
+const CASHADDR_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// Bitcoin Cash cashaddr for a 160 bit hash, kind 0 is P2PKH and 1 is P2SH
+pub fn cashaddr(prefix: &str, kind: u8, hash: &[u8; 20]) -> String {
+    let mut payload = vec![kind << 3];
+    payload.extend_from_slice(hash);
+    let mut data = convert_bits(&payload, 8, 5);
+
+    let mut checksum_input: Vec<u8> = prefix.bytes().map(|b| b & 0x1f).collect();
+    checksum_input.push(0);
+    checksum_input.extend_from_slice(&data);
+    checksum_input.extend_from_slice(&[0; 8]);
+    let checksum = cashaddr_polymod(&checksum_input);
+    data.extend((0..8).map(|i| ((checksum >> (5 * (7 - i))) & 0x1f) as u8));
+
+    let encoded: String = data.iter().map(|d| CASHADDR_CHARSET[*d as usize] as char).collect();
+    format!("{}:{}", prefix, encoded)
+}
+
+fn cashaddr_polymod(values: &[u8]) -> u64 {
+    const GENERATORS: [u64; 5] = [0x98f2bc8e61, 0x79b76d99e2, 0xf33e5fb3c4, 0xae2eabe2a8, 0x1e4f43e470];
+    let mut c: u64 = 1;
+    for value in values {
+        let top = c >> 35;
+        c = ((c & 0x07ffffffff) << 5) ^ *value as u64;
+        for (i, generator) in GENERATORS.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+    }
+    c ^ 1
+}
+
+// Regroups bits, padding the final group with zeros
+fn convert_bits(data: &[u8], from: u32, to: u32) -> Vec<u8> {
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+    let mut output = vec![];
+    let mask = (1 << to) - 1;
+    for value in data {
+        accumulator = (accumulator << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            output.push(((accumulator >> bits) & mask) as u8);
+        }
+    }
+    if bits > 0 {
+        output.push(((accumulator << (to - bits)) & mask) as u8);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::address::AddressType;
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::transaction::decode_hex;
+    use crate::keys::encoding::*;
+    use secp256k1::PublicKey;
+
+    // The generator point, i.e. the public key of private key 1
+    fn generator() -> PublicKey {
+        let bytes = decode_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        PublicKey::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn bitcoin_addresses_test() {
+        let key = generator();
+        assert_eq!(encode_address(&BlockChainNames::Bitcoin, ScriptType::P2PKH, &key).unwrap(), "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+        assert_eq!(encode_address(&BlockChainNames::Bitcoin, ScriptType::P2SHP2WPKH, &key).unwrap(), "3JvL6Ymt8MVWiCNHC7oWU6nLeHNJKLZGLN");
+        assert_eq!(encode_address(&BlockChainNames::Bitcoin, ScriptType::P2WPKH, &key).unwrap(), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        assert_eq!(AddressType::from_hash(&encode_address(&BlockChainNames::Bitcoin, ScriptType::P2TR, &key).unwrap()), AddressType::P2TR);
+    }
+
+    #[test]
+    fn other_chain_addresses_test() {
+        let key = generator();
+        assert!(encode_address(&BlockChainNames::Litecoin, ScriptType::P2PKH, &key).unwrap().starts_with('L'));
+        assert!(encode_address(&BlockChainNames::Litecoin, ScriptType::P2WPKH, &key).unwrap().starts_with("ltc1q"));
+        assert!(encode_address(&BlockChainNames::Dogecoin, ScriptType::P2PKH, &key).unwrap().starts_with('D'));
+        assert!(encode_address(&BlockChainNames::Dogecoin, ScriptType::P2WPKH, &key).is_err());
+        assert!(encode_address(&BlockChainNames::Dash, ScriptType::P2PKH, &key).unwrap().starts_with('X'));
+        assert_eq!(encode_address(&BlockChainNames::Ethereum, ScriptType::P2PKH, &key).unwrap(), "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf");
+        assert!(encode_address(&BlockChainNames::BitcoinCash, ScriptType::P2WPKH, &key).is_err());
+    }
+
+    #[test]
+    fn cashaddr_test() {
+        let hash: [u8; 20] = decode_hex("f5bf48b397dae70be82b3cca4793f8eb2b6cdac9").unwrap().try_into().unwrap();
+        assert_eq!(cashaddr("bitcoincash", 0, &hash), "bitcoincash:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2");
+        assert_eq!(ScriptType::from_purpose(84).unwrap(), ScriptType::P2WPKH);
+        assert!(ScriptType::from_purpose(45).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod bip32;
+pub mod descriptor;
+pub mod encoding;
+pub mod scan;
\ No newline at end of file
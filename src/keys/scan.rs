@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use getset::{CopyGetters, Getters};
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::common::address::Address;
+use crate::common::block::Block;
+use crate::common::blockchain::BlockChainNames;
+use crate::common::transaction::Transaction;
+use crate::keys::bip32::ExtendedPublicKey;
+use crate::keys::encoding::{encode_address, ScriptType};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct DerivedAddress {
+    #[getset(get_copy = "pub")]
+    change: bool,
+
+    #[getset(get_copy = "pub")]
+    index: u32,
+
+    #[getset(get = "pub")]
+    address_hash: String,
+
+    #[getset(get_copy = "pub")]
+    used: bool
+}
+
+impl DerivedAddress {
+    // Relative to the account key, e.g. "1/5"
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.change as u8, self.index)
+    }
+}
+
+// Derives receive (0/i) and change (1/i) addresses from an account level key
+#[derive(Debug, Clone)]
+pub struct XpubScanner {
+    receive: ExtendedPublicKey,
+    change: ExtendedPublicKey,
+    chain: BlockChainNames,
+    script_type: ScriptType,
+    gap_limit: u32
+}
+
+impl XpubScanner {
+    // Falls back to the script type the key's version bytes imply, then to P2PKH
+    pub fn new(xpub: &str, chain: BlockChainNames, script_type: Option<ScriptType>) -> Result<Self, Box<dyn Error>> {
+        let account = ExtendedPublicKey::parse(xpub)?;
+        let script_type = script_type.or(account.script_type()).unwrap_or(ScriptType::P2PKH);
+        Ok(XpubScanner {
+            receive: account.derive_child(0)?,
+            change: account.derive_child(1)?,
+            chain,
+            script_type,
+            gap_limit: 20
+        })
+    }
+
+    pub fn set_gap_limit(&mut self, gap_limit: u32) -> &mut Self {
+        self.gap_limit = gap_limit.max(1);
+        self
+    }
+
+    pub fn script_type(&self) -> ScriptType {
+        self.script_type
+    }
+
+    pub fn address_at(&self, change: bool, index: u32) -> Result<String, Box<dyn Error>> {
+        encode_address(&self.chain, self.script_type, self.parent(change).derive_child(index)?.public_key())
+    }
+
+    // Walks each chain until `gap_limit` consecutive addresses are unused, returning
+    // every address derived along the way. Indices without a valid key are skipped.
+    pub fn scan<F: Fn(&str) -> bool>(&self, is_used: F) -> Result<Vec<DerivedAddress>, Box<dyn Error>> {
+        let mut derived = vec![];
+        for change in [false, true] {
+            let mut gap = 0;
+            let mut index = 0;
+            while gap < self.gap_limit {
+                let Some(child) = self.parent(change).try_derive_child(index)? else {
+                    index += 1;
+                    continue
+                };
+                let address_hash = encode_address(&self.chain, self.script_type, child.public_key())?;
+                let used = is_used(&address_hash);
+                gap = if used { 0 } else { gap + 1 };
+                derived.push(DerivedAddress { change, index, address_hash, used });
+                index += 1;
+            }
+        }
+        Ok(derived)
+    }
+
+    // Rebuilds the watch-only wallet's used addresses from ingested blocks
+    pub fn scan_blocks(&self, blocks: &[Block]) -> Result<Vec<Address>, Box<dyn Error>> {
+        let mut transactions: HashMap<&String, Vec<&Transaction>> = HashMap::new();
+        for block in blocks {
+            for transaction in block.transactions() {
+                for amount in transaction.transaction_amounts() {
+                    let seen = transactions.entry(amount.address_hash()).or_default();
+                    if !seen.iter().any(|t| t.hash() == transaction.hash()) {
+                        seen.push(transaction);
+                    }
+                }
+            }
+        }
+
+        let derived = self.scan(|hash| transactions.contains_key(&hash.to_string()))?;
+        Ok(derived.iter().filter(|d| d.used).map(|d| {
+            let mut address = Address::new(d.address_hash.clone());
+            for transaction in &transactions[&d.address_hash] {
+                // Coinbase outputs are added as miner rewards, everything else with is_miner false
+                address.add_transaction(transaction);
+            }
+            address
+        }).collect())
+    }
+
+    // Private Methods:
+    fn parent(&self, change: bool) -> &ExtendedPublicKey {
+        if change { &self.change } else { &self.receive }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::block::Block;
+    use crate::common::blockchain::BlockChainNames;
+    use crate::common::transaction::{Transaction, TransactionAmount};
+    use crate::keys::encoding::ScriptType;
+    use crate::keys::scan::*;
+
+    // BIP84 and BIP86 test vector account keys
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    const BIP86_XPUB: &str = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+
+    #[test]
+    fn derive_addresses_test() {
+        let bip84 = XpubScanner::new(ZPUB, BlockChainNames::Bitcoin, None).unwrap();
+        assert_eq!(bip84.script_type(), ScriptType::P2WPKH);
+        assert_eq!(bip84.address_at(false, 0).unwrap(), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        assert_eq!(bip84.address_at(false, 1).unwrap(), "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g");
+        assert_eq!(bip84.address_at(true, 0).unwrap(), "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+
+        let bip86 = XpubScanner::new(BIP86_XPUB, BlockChainNames::Bitcoin, Some(ScriptType::P2TR)).unwrap();
+        assert_eq!(bip86.address_at(false, 0).unwrap(), "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+    }
+
+    #[test]
+    fn gap_limit_scan_test() {
+        let mut scanner = XpubScanner::new(ZPUB, BlockChainNames::Bitcoin, None).unwrap();
+        scanner.set_gap_limit(3);
+        let used_receive = scanner.address_at(false, 2).unwrap();
+        let beyond_gap = scanner.address_at(false, 9).unwrap();
+        let used_change = scanner.address_at(true, 0).unwrap();
+
+        let mut block = Block::new("blocky_hash".to_string(), 100, 1);
+        let mut transaction = Transaction::new_from_block("hashy".to_string(), false, &block);
+        transaction.set_transaction_amounts(vec![
+            TransactionAmount::new(1.0, used_receive.clone(), "hashy".to_string(), 100, 0),
+            TransactionAmount::new(2.0, beyond_gap, "hashy".to_string(), 100, 1),
+            TransactionAmount::new(0.5, used_change.clone(), "hashy".to_string(), 100, 2),
+        ]);
+        block.set_transactions(vec![transaction]);
+
+        let derived = scanner.scan(|hash| hash == used_receive || hash == used_change).unwrap();
+        assert_eq!(derived.iter().filter(|d| !d.change()).count(), 6);
+        assert_eq!(derived.iter().filter(|d| d.change()).count(), 4);
+        assert_eq!(derived[2].path(), "0/2");
+        assert!(derived[2].used());
+
+        let addresses = scanner.scan_blocks(&[block]).unwrap();
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(addresses[0].hash(), &used_receive);
+        assert_eq!(addresses[0].coin_total(), 1.0);
+        assert_eq!(addresses[1].hash(), &used_change);
+    }
+}
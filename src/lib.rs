@@ -1,4 +1,9 @@
 pub mod common;
+pub mod analytics;
+pub mod keys;
+
+#[cfg(test)]
+pub(crate) mod test_fixtures;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,52 @@
+// Builders shared by the unit tests
+use crate::common::address::Address;
+use crate::common::block::Block;
+use crate::common::transaction::{Transaction, TransactionAmount};
+
+pub(crate) const DATE: i64 = 123456789;
+pub(crate) const HEIGHT: i64 = 420;
+
+// Spend of output `vin_index` of `funding_hash`, `amount` is given positive
+pub(crate) fn spend_of(funding_hash: &str, vin_index: i32, address: &str, transaction_hash: &str, amount: f64, date: i64) -> TransactionAmount {
+    let mut input = TransactionAmount::new(-amount, address.to_string(), transaction_hash.to_string(), date, -1);
+    input.set_vin_hash(Some(funding_hash.to_string()));
+    input.set_vin_index(vin_index);
+    input
+}
+
+// Spend of an output nothing else in the test refers to
+pub(crate) fn input(address: &str, transaction_hash: &str, amount: f64, date: i64) -> TransactionAmount {
+    spend_of("previous", 0, address, transaction_hash, amount, date)
+}
+
+// Transaction in block HEIGHT whose inputs spend previous:0, previous:1, ...
+pub(crate) fn transaction_at(hash: &str, date: i64, is_coinbase: bool, inputs: &[(&str, f64)], outputs: &[(&str, f64)]) -> Transaction {
+    let mut transaction = Transaction::new(hash.to_string(), is_coinbase, date, "blocky_hash".to_string(), HEIGHT);
+    let mut amounts = vec![];
+    for (i, (address, amount)) in inputs.iter().enumerate() {
+        amounts.push(spend_of("previous", i as i32, address, hash, *amount, date));
+    }
+    for (i, (address, amount)) in outputs.iter().enumerate() {
+        amounts.push(TransactionAmount::new(*amount, address.to_string(), hash.to_string(), date, i as i32));
+    }
+    transaction.set_transaction_amounts(amounts);
+    transaction
+}
+
+pub(crate) fn transaction(inputs: &[(&str, f64)], outputs: &[(&str, f64)]) -> Transaction {
+    transaction_at("hashy", DATE, false, inputs, outputs)
+}
+
+pub(crate) fn block(transactions: Vec<Transaction>) -> Block {
+    let mut block = Block::new("blocky_hash".to_string(), DATE, HEIGHT);
+    block.set_transactions(transactions);
+    block
+}
+
+pub(crate) fn address(hash: &str, amounts: Vec<TransactionAmount>) -> Address {
+    let mut address = Address::new(hash.to_string());
+    for amount in amounts {
+        address.add_transaction_amount(amount, false);
+    }
+    address
+}